@@ -0,0 +1,465 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    glob,
+    memory::MemoryFile,
+    progress::ProgressCallback,
+    walk::{is_plain, normalize, Input},
+    CancellationToken, CompressionLevel, Compressor, Error, Format, Progress,
+};
+
+/// Configures a [`Compressor`], so the same options can be reused across calls
+///
+/// # Example
+///
+/// ```
+/// use comprexor::{CompressionLevel, CompressorBuilder, Format};
+///
+/// # let dir = tempfile::tempdir().unwrap();
+/// # std::env::set_current_dir(dir.path()).unwrap();
+/// # std::fs::create_dir("./folder-or-file-to-compress").unwrap();
+/// let compressor = CompressorBuilder::new("./folder-or-file-to-compress", "./compacted-archive.zip")
+///     .format(Format::Zip)
+///     .level(CompressionLevel::Maximum)
+///     .root_name("release")
+///     .follow_symlinks(false)
+///     .build()
+///     .unwrap();
+///
+/// compressor.run().unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, PartialOrd, Hash)]
+pub struct CompressorBuilder {
+    compressor: Compressor,
+}
+
+impl CompressorBuilder {
+    /// Create a builder for a compressor of `input` into `output`, with the default options
+    #[must_use]
+    pub fn new<I: AsRef<Path>, O: AsRef<Path>>(input: I, output: O) -> Self {
+        Self {
+            compressor: Compressor::new(input, output),
+        }
+    }
+
+    /// Create a builder for an archive without inputs from the file system, made of the files
+    /// added with [`CompressorBuilder::add_bytes`]
+    ///
+    /// The compressor has no output path either, write the archive with [`Compressor::compress_to_vec`]
+    /// or [`Compressor::compress_to_writer`]. Inputs from the file system can still be added.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CompressionLevel, CompressorBuilder, Extractor, Format};
+    /// use std::path::Path;
+    ///
+    /// let archive = CompressorBuilder::in_memory()
+    ///     .format(Format::Zip)
+    ///     .add_bytes("bin/run.sh", "#!/bin/sh\necho hello\n", 0o755)
+    ///     .add_bytes("README.md", "# Hello\n", 0o644)
+    ///     .build()
+    ///     .unwrap()
+    ///     .compress_to_vec(CompressionLevel::Default)
+    ///     .unwrap();
+    ///
+    /// let files = Extractor::in_memory()
+    ///     .extract_to_map_from_reader(archive.as_slice())
+    ///     .unwrap();
+    /// assert_eq!(files[Path::new("README.md")], b"# Hello\n");
+    /// ```
+    #[must_use]
+    pub fn in_memory() -> Self {
+        let mut compressor = Compressor::new("", "");
+        compressor.inputs.clear();
+        Self { compressor }
+    }
+
+    /// Set the format of the archive to create, defaults to [`Format::Gzip`]
+    #[must_use]
+    pub fn format(mut self, format: Format) -> Self {
+        self.compressor.format = format;
+        self
+    }
+
+    /// Set the compression level used by [`Compressor::run`], defaults to [`CompressionLevel::Default`]
+    #[must_use]
+    pub fn level(mut self, level: CompressionLevel) -> Self {
+        self.compressor.level = level;
+        self
+    }
+
+    /// Set the name of the input inside the archive
+    ///
+    /// By default a directory is stored under its own name and a file under its path.
+    /// This only renames the input given to [`CompressorBuilder::new`], use
+    /// [`CompressorBuilder::add_input_as`] to name the other ones.
+    #[must_use]
+    pub fn root_name<P: AsRef<Path>>(mut self, name: P) -> Self {
+        if let Some(input) = self.compressor.inputs.first_mut() {
+            input.name = Some(name.as_ref().to_path_buf());
+        }
+        self
+    }
+
+    /// Add another file or directory to the archive, stored under its own name like the first input
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CompressorBuilder, Extractor};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::create_dir_all("./a").unwrap();
+    /// # std::fs::create_dir_all("./c").unwrap();
+    /// # std::fs::write("./a/file.txt", "a").unwrap();
+    /// # std::fs::write("./b.txt", "b").unwrap();
+    /// # std::fs::write("./c/file.txt", "c").unwrap();
+    /// // Like `tar czf out.tar.gz a/ b.txt c/`, with `c` stored as `data/c`
+    /// CompressorBuilder::new("./a", "./out.tar.gz")
+    ///     .add_input("./b.txt")
+    ///     .add_input_as("./c", "data/c")
+    ///     .build()
+    ///     .unwrap()
+    ///     .run()
+    ///     .unwrap();
+    ///
+    /// Extractor::new("./out.tar.gz", "./output").extract().unwrap();
+    /// assert_eq!(std::fs::read_to_string("./output/a/file.txt").unwrap(), "a");
+    /// assert_eq!(std::fs::read_to_string("./output/b.txt").unwrap(), "b");
+    /// assert_eq!(std::fs::read_to_string("./output/data/c/file.txt").unwrap(), "c");
+    /// ```
+    #[must_use]
+    pub fn add_input<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.compressor.inputs.push(Input::new(path.as_ref()));
+        self
+    }
+
+    /// Add another file or directory to the archive, stored under `name`
+    #[must_use]
+    pub fn add_input_as<P: AsRef<Path>, N: AsRef<Path>>(mut self, path: P, name: N) -> Self {
+        self.compressor.inputs.push(Input {
+            path: path.as_ref().to_path_buf(),
+            name: Some(name.as_ref().to_path_buf()),
+        });
+        self
+    }
+
+    /// Add a file with `content` and the unix permissions of `mode` to the archive, stored at `path`
+    ///
+    /// The files added from memory are stored after the inputs, in the order they were added,
+    /// and dated from the time of the compression. `path` must be relative without `..`,
+    /// [`CompressorBuilder::build`] fails with [`Error::InvalidOption`] otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CompressorBuilder, Error};
+    ///
+    /// let result = CompressorBuilder::in_memory()
+    ///     .add_bytes("a/../b.txt", "content", 0o644)
+    ///     .build();
+    /// assert!(matches!(result, Err(Error::InvalidOption { option: "add_bytes", .. })));
+    /// ```
+    #[must_use]
+    pub fn add_bytes<P: AsRef<Path>, C: Into<Vec<u8>>>(
+        mut self,
+        path: P,
+        content: C,
+        mode: u32,
+    ) -> Self {
+        self.compressor.files.push(MemoryFile {
+            name: path.as_ref().to_path_buf(),
+            content: content.into(),
+            mode,
+        });
+        self
+    }
+
+    /// Archive the files symlinks point to instead of the symlinks themselves, defaults to `true`
+    #[must_use]
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.compressor.follow_symlinks = follow;
+        self
+    }
+
+    /// Only archive the files and directories matching `pattern` inside of input directories,
+    /// can be called multiple times
+    ///
+    /// Patterns use the `.gitignore` syntax and are matched against paths relative to the
+    /// input directory: a pattern without `/` matches names at any depth, `**` matches any
+    /// number of directories and a trailing `/` only matches directories. A matching file is
+    /// included, and so is everything inside a matching directory. Other directories are
+    /// kept when they hold included entries. Inputs that are files are always archived.
+    #[must_use]
+    pub fn include<P: Into<String>>(mut self, pattern: P) -> Self {
+        self.compressor.include.push(pattern.into());
+        self
+    }
+
+    /// Leave the files and directories matching `pattern` out of the archive, can be called multiple times
+    ///
+    /// Patterns use the same syntax as [`CompressorBuilder::include`], an excluded directory
+    /// is left out with all of its content. Exclusion wins over inclusion.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CompressorBuilder, Extractor};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::create_dir_all("./project/src").unwrap();
+    /// # std::fs::create_dir_all("./project/target/debug").unwrap();
+    /// # std::fs::write("./project/src/main.rs", "fn main() {}").unwrap();
+    /// # std::fs::write("./project/src/main.rs.bak", "").unwrap();
+    /// # std::fs::write("./project/target/debug/project", "").unwrap();
+    /// CompressorBuilder::new("./project", "./project.tar.gz")
+    ///     .exclude("/target/")
+    ///     .exclude("*.bak")
+    ///     .build()
+    ///     .unwrap()
+    ///     .run()
+    ///     .unwrap();
+    ///
+    /// Extractor::new("./project.tar.gz", "./output").extract().unwrap();
+    /// assert!(std::path::Path::new("./output/project/src/main.rs").exists());
+    /// assert!(!std::path::Path::new("./output/project/src/main.rs.bak").exists());
+    /// assert!(!std::path::Path::new("./output/project/target").exists());
+    /// ```
+    #[must_use]
+    pub fn exclude<P: Into<String>>(mut self, pattern: P) -> Self {
+        self.compressor.exclude.push(pattern.into());
+        self
+    }
+
+    /// Leave out what the `.gitignore` and `.ignore` files found in the input directories match, defaults to `false`
+    ///
+    /// The files apply to the directory they are in and its content, the rules of `.ignore`
+    /// files and of deeper directories take precedence. `.git` directories are left out too.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CompressorBuilder, Extractor};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::create_dir_all("./project/node_modules/dep").unwrap();
+    /// # std::fs::write("./project/index.js", "").unwrap();
+    /// # std::fs::write("./project/debug.log", "").unwrap();
+    /// # std::fs::write("./project/keep.log", "").unwrap();
+    /// # std::fs::write("./project/node_modules/dep/index.js", "").unwrap();
+    /// std::fs::write("./project/.gitignore", "node_modules/\n*.log\n!keep.log\n").unwrap();
+    ///
+    /// CompressorBuilder::new("./project", "./project.tar.gz")
+    ///     .respect_ignore_files(true)
+    ///     .build()
+    ///     .unwrap()
+    ///     .run()
+    ///     .unwrap();
+    ///
+    /// Extractor::new("./project.tar.gz", "./output").extract().unwrap();
+    /// assert!(std::path::Path::new("./output/project/index.js").exists());
+    /// assert!(std::path::Path::new("./output/project/keep.log").exists());
+    /// assert!(!std::path::Path::new("./output/project/debug.log").exists());
+    /// assert!(!std::path::Path::new("./output/project/node_modules").exists());
+    /// ```
+    #[must_use]
+    pub fn respect_ignore_files(mut self, respect: bool) -> Self {
+        self.compressor.respect_ignore_files = respect;
+        self
+    }
+
+    /// Store the content of directory inputs at the root of the archive instead of under their name, defaults to `false`
+    ///
+    /// Directories named with [`CompressorBuilder::root_name`] or [`CompressorBuilder::add_input_as`]
+    /// are still stored under that name.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CompressorBuilder, Extractor};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::create_dir("./folder").unwrap();
+    /// # std::fs::write("./folder/file.txt", "content").unwrap();
+    /// CompressorBuilder::new("./folder", "./archive.tar.gz")
+    ///     .contents_at_root(true)
+    ///     .build()
+    ///     .unwrap()
+    ///     .run()
+    ///     .unwrap();
+    ///
+    /// Extractor::new("./archive.tar.gz", "./output").extract().unwrap();
+    /// assert!(std::path::Path::new("./output/file.txt").exists());
+    /// ```
+    #[must_use]
+    pub fn contents_at_root(mut self, contents_at_root: bool) -> Self {
+        self.compressor.contents_at_root = contents_at_root;
+        self
+    }
+
+    /// Make archives that only depend on the content of the inputs, defaults to `false`
+    ///
+    /// Two runs on the same files give the same bytes: directory entries are sorted by name,
+    /// owners are left out, permissions are `755` for directories and executables and `644`
+    /// for other files, and every entry is dated from the `SOURCE_DATE_EPOCH` environment
+    /// variable, or a fixed date when it is not set. The gzip header has no date either way.
+    /// Compressing on a single thread or on several threads gives different bytes, see
+    /// [`CompressorBuilder::threads`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::CompressorBuilder;
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::create_dir("./folder").unwrap();
+    /// # std::fs::write("./folder/file.txt", "content").unwrap();
+    /// for output in ["./first.tar.gz", "./second.tar.gz"] {
+    ///     CompressorBuilder::new("./folder", output)
+    ///         .reproducible(true)
+    ///         .build()
+    ///         .unwrap()
+    ///         .run()
+    ///         .unwrap();
+    /// }
+    /// assert_eq!(std::fs::read("./first.tar.gz").unwrap(), std::fs::read("./second.tar.gz").unwrap());
+    /// ```
+    #[must_use]
+    pub fn reproducible(mut self, reproducible: bool) -> Self {
+        self.compressor.reproducible = reproducible;
+        self
+    }
+
+    /// Compress gzip archives on `threads` threads, defaults to 1
+    ///
+    /// The archive is split into blocks that are compressed at the same time, like pigz does.
+    /// Each block uses the end of the previous one as a preset dictionary, so the archive is a
+    /// regular gzip file about the size of one compressed on a single thread. Its blocks depend
+    /// on each other, [`Extractor::threads`](crate::Extractor::threads) decompresses it on a
+    /// single thread. Other formats are always compressed on a single thread.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CompressorBuilder, Extractor};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::create_dir("./folder").unwrap();
+    /// # std::fs::write("./folder/file.txt", "content ".repeat(100_000)).unwrap();
+    /// let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    /// CompressorBuilder::new("./folder", "./archive.tar.gz")
+    ///     .threads(threads)
+    ///     .build()
+    ///     .unwrap()
+    ///     .run()
+    ///     .unwrap();
+    ///
+    /// Extractor::new("./archive.tar.gz", "./output").extract().unwrap();
+    /// assert_eq!(
+    ///     std::fs::read("./output/folder/file.txt").unwrap(),
+    ///     std::fs::read("./folder/file.txt").unwrap()
+    /// );
+    /// ```
+    #[must_use]
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.compressor.threads = threads;
+        self
+    }
+
+    /// Call `callback` with the [`Progress`] of the compression as the inputs are read and the archive is written
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CompressorBuilder, Progress};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::create_dir("./folder").unwrap();
+    /// # std::fs::write("./folder/file.txt", [b'a'; 100_000]).unwrap();
+    /// let compressor = CompressorBuilder::new("./folder", "./archive.tar.gz")
+    ///     .on_progress(|progress: &Progress| {
+    ///         if let (Some(entry), Some(total)) = (progress.entry(), progress.total_bytes()) {
+    ///             println!("{}: {}/{total} bytes", entry.display(), progress.bytes_read());
+    ///         }
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// compressor.run().unwrap();
+    /// ```
+    #[must_use]
+    pub fn on_progress<F: Fn(&Progress) + Send + Sync + 'static>(mut self, callback: F) -> Self {
+        self.compressor.progress = Some(ProgressCallback::new(callback));
+        self
+    }
+
+    /// Stop the compression when `token` is cancelled, see [`CancellationToken`]
+    ///
+    /// The token is checked before every entry and every chunk of data. The partial
+    /// archive is then removed and [`Error::Cancelled`] is returned.
+    #[must_use]
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.compressor.cancel = Some(token);
+        self
+    }
+
+    /// Create the configured compressor
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the level is not supported by the format,
+    /// the name of an input is empty, the path of a file added from memory is absolute or has
+    /// a `..` component, a pattern is invalid or the number of threads is 0
+    pub fn build(self) -> Result<Compressor, Error> {
+        let compressor = self.compressor;
+        compressor.format.check_level(&compressor.level)?;
+        if compressor.threads == 0 {
+            return Err(Error::InvalidOption {
+                option: "threads",
+                reason: "at least one thread is needed".to_string(),
+            });
+        }
+        glob::compile("include", &compressor.include)?;
+        glob::compile("exclude", &compressor.exclude)?;
+        for (index, input) in compressor.inputs.iter().enumerate() {
+            let Some(name) = &input.name else {
+                continue;
+            };
+            if normalize(name) == PathBuf::new() {
+                return Err(Error::InvalidOption {
+                    option: if index == 0 {
+                        "root_name"
+                    } else {
+                        "add_input_as"
+                    },
+                    reason: format!("`{}` has no usable component", name.display()),
+                });
+            }
+        }
+        for file in &compressor.files {
+            if !is_plain(&file.name) {
+                return Err(Error::InvalidOption {
+                    option: "add_bytes",
+                    reason: format!(
+                        "`{}` must be a relative path without `..`",
+                        file.name.display()
+                    ),
+                });
+            }
+            if normalize(&file.name) == PathBuf::new() {
+                return Err(Error::InvalidOption {
+                    option: "add_bytes",
+                    reason: format!("`{}` has no usable component", file.name.display()),
+                });
+            }
+        }
+        Ok(compressor)
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    hash::{Hash, Hasher},
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::error::Interrupt;
+
+/// A token to stop a running compression or extraction from another thread
+///
+/// Clones share the same state, cancelling one cancels them all. Tokens are compared
+/// and hashed by identity, two tokens are equal when one is a clone of the other.
+///
+/// # Example
+///
+/// ```
+/// use comprexor::{CancellationToken, CompressorBuilder, Error};
+///
+/// # let dir = tempfile::tempdir().unwrap();
+/// # std::env::set_current_dir(dir.path()).unwrap();
+/// # std::fs::create_dir("./folder").unwrap();
+/// # std::fs::write("./folder/file.txt", "content").unwrap();
+/// let token = CancellationToken::new();
+/// let compressor = CompressorBuilder::new("./folder", "./archive.tar.gz")
+///     .cancellation_token(token.clone())
+///     .build()
+///     .unwrap();
+///
+/// token.cancel();
+/// assert!(matches!(compressor.run(), Err(Error::Cancelled { .. })));
+/// // The partial archive is removed
+/// assert!(!std::path::Path::new("./archive.tar.gz").exists());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token that is not cancelled yet
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request the operations using this token to stop
+    ///
+    /// They stop before their next entry or chunk of data and return [`Error::Cancelled`](crate::Error::Cancelled)
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Check if [`CancellationToken::cancel`] was called on this token or one of its clones
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fail once the token is cancelled, the error becomes [`Error::Cancelled`](crate::Error::Cancelled)
+    pub(crate) fn check(&self) -> io::Result<()> {
+        if self.is_cancelled() {
+            Err(Interrupt::Cancelled.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn address(&self) -> *const AtomicBool {
+        Arc::as_ptr(&self.0)
+    }
+}
+
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &Self) -> bool {
+        self.address() == other.address()
+    }
+}
+
+impl Eq for CancellationToken {}
+
+impl PartialOrd for CancellationToken {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.address().partial_cmp(&other.address())
+    }
+}
+
+impl Hash for CancellationToken {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.address().hash(state);
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use crate::Limit;
+
+/// The step of an operation during which an [`Error`] happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// Reading the input and writing it into the tar archive
+    BuildingTar,
+    /// Compressing the tar archive
+    Encoding,
+    /// Decompressing the input archive
+    Decoding,
+    /// Writing the archive entries to the output location
+    Unpacking,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::BuildingTar => write!(f, "building tar"),
+            Phase::Encoding => write!(f, "encoding"),
+            Phase::Decoding => write!(f, "decoding"),
+            Phase::Unpacking => write!(f, "unpacking"),
+        }
+    }
+}
+
+/// The error type returned by [`Compressor`](crate::Compressor) and [`Extractor`](crate::Extractor)
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The compression level is outside of the supported range (0-9)
+    InvalidLevel(u32),
+    /// An option has a value that can not be used
+    InvalidOption {
+        option: &'static str,
+        reason: String,
+    },
+    /// The input path does not exist
+    InputNotFound { path: PathBuf },
+    /// The input is neither a file, a symlink or a directory
+    UnsupportedInputKind { path: PathBuf },
+    /// The input archive could not be decoded
+    CorruptArchive {
+        path: PathBuf,
+        phase: Phase,
+        source: io::Error,
+    },
+    /// An archive entry would be written outside of the output directory
+    PathTraversal { path: PathBuf },
+    /// The input is an archive format that can not be decoded, like `"zstd"`
+    UnsupportedFormat { path: PathBuf, format: &'static str },
+    /// The format of the input could not be recognized
+    UnknownFormat { path: PathBuf },
+    /// The archive at `path` has no file at `entry`
+    EntryNotFound { path: PathBuf, entry: PathBuf },
+    /// An archive entry would be written where something already exists, see [`OverwritePolicy::Fail`](crate::OverwritePolicy::Fail)
+    OutputExists { path: PathBuf },
+    /// The operation was stopped with a [`CancellationToken`](crate::CancellationToken)
+    Cancelled { path: PathBuf, phase: Phase },
+    /// The extraction went over one of its [`Limits`](crate::Limits)
+    LimitExceeded { path: PathBuf, limit: Limit },
+    /// An I/O error happened while reading or writing `path`
+    Io {
+        path: PathBuf,
+        phase: Phase,
+        source: io::Error,
+    },
+}
+
+impl Error {
+    /// Wraps an I/O error that happened while working on `path`
+    ///
+    /// Invalid data found while decoding or unpacking is reported as [`Error::CorruptArchive`]
+    pub(crate) fn io(path: impl Into<PathBuf>, phase: Phase, source: io::Error) -> Self {
+        let path = path.into();
+        match Interrupt::find(&source) {
+            Some(Interrupt::Cancelled) => return Error::Cancelled { path, phase },
+            Some(Interrupt::LimitExceeded(limit)) => {
+                return Error::LimitExceeded {
+                    path,
+                    limit: *limit,
+                }
+            }
+            None => {}
+        }
+        match (phase, source.kind()) {
+            (
+                Phase::Decoding | Phase::Unpacking,
+                io::ErrorKind::InvalidData
+                | io::ErrorKind::InvalidInput
+                | io::ErrorKind::UnexpectedEof,
+            ) => Error::CorruptArchive {
+                path,
+                phase,
+                source,
+            },
+            (Phase::BuildingTar, io::ErrorKind::NotFound) => Error::InputNotFound { path },
+            _ => Error::Io {
+                path,
+                phase,
+                source,
+            },
+        }
+    }
+
+    /// Get the path related to the error, if any
+    #[must_use]
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::InvalidLevel(_) | Error::InvalidOption { .. } => None,
+            Error::InputNotFound { path }
+            | Error::UnsupportedInputKind { path }
+            | Error::CorruptArchive { path, .. }
+            | Error::PathTraversal { path }
+            | Error::UnsupportedFormat { path, .. }
+            | Error::UnknownFormat { path }
+            | Error::EntryNotFound { path, .. }
+            | Error::OutputExists { path }
+            | Error::Cancelled { path, .. }
+            | Error::LimitExceeded { path, .. }
+            | Error::Io { path, .. } => Some(path),
+        }
+    }
+
+    /// Get the phase of the operation in which the error happened, if known
+    #[must_use]
+    pub fn phase(&self) -> Option<Phase> {
+        match self {
+            Error::CorruptArchive { phase, .. }
+            | Error::Cancelled { phase, .. }
+            | Error::Io { phase, .. } => Some(*phase),
+            Error::InputNotFound { .. } | Error::UnsupportedInputKind { .. } => {
+                Some(Phase::BuildingTar)
+            }
+            Error::PathTraversal { .. }
+            | Error::LimitExceeded { .. }
+            | Error::OutputExists { .. } => Some(Phase::Unpacking),
+            Error::UnsupportedFormat { .. }
+            | Error::UnknownFormat { .. }
+            | Error::EntryNotFound { .. } => Some(Phase::Decoding),
+            Error::InvalidLevel(_) | Error::InvalidOption { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidLevel(level) => write!(
+                f,
+                "Invalid compression level: {level}, must be between 0 and 9"
+            ),
+            Error::InvalidOption { option, reason } => {
+                write!(f, "Invalid value for option `{option}`: {reason}")
+            }
+            Error::InputNotFound { path } => {
+                write!(f, "Input `{}` does not exist", path.display())
+            }
+            Error::UnsupportedInputKind { path } => write!(
+                f,
+                "Input `{}` is neither a file, symlink or a directory",
+                path.display()
+            ),
+            Error::CorruptArchive {
+                path,
+                phase,
+                source,
+            } => write!(
+                f,
+                "Corrupt archive `{}` while {phase}: {source}",
+                path.display()
+            ),
+            Error::PathTraversal { path } => write!(
+                f,
+                "Archive entry `{}` points outside of the output directory",
+                path.display()
+            ),
+            Error::UnsupportedFormat { path, format } => write!(
+                f,
+                "Archive `{}` is in the unsupported {format} format",
+                path.display()
+            ),
+            Error::UnknownFormat { path } => {
+                write!(f, "Could not recognize the format of `{}`", path.display())
+            }
+            Error::EntryNotFound { path, entry } => write!(
+                f,
+                "Archive `{}` has no file `{}`",
+                path.display(),
+                entry.display()
+            ),
+            Error::OutputExists { path } => write!(f, "Output `{}` already exists", path.display()),
+            Error::Cancelled { path, phase } => {
+                write!(f, "Cancelled while {phase} `{}`", path.display())
+            }
+            Error::LimitExceeded { path, limit } => {
+                write!(f, "Extraction of `{}` exceeded {limit}", path.display())
+            }
+            Error::Io {
+                path,
+                phase,
+                source,
+            } => write!(
+                f,
+                "I/O error on `{}` while {phase}: {source}",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::CorruptArchive { source, .. } | Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(value: Error) -> Self {
+        let kind = match &value {
+            Error::InvalidLevel(_) | Error::InvalidOption { .. } => io::ErrorKind::InvalidInput,
+            Error::InputNotFound { .. } | Error::EntryNotFound { .. } => io::ErrorKind::NotFound,
+            Error::CorruptArchive { .. } | Error::PathTraversal { .. } => {
+                io::ErrorKind::InvalidData
+            }
+            Error::Io { source, .. } => source.kind(),
+            Error::UnsupportedInputKind { .. } | Error::UnsupportedFormat { .. } => {
+                io::ErrorKind::Unsupported
+            }
+            Error::UnknownFormat { .. } => io::ErrorKind::InvalidData,
+            Error::OutputExists { .. } => io::ErrorKind::AlreadyExists,
+            Error::Cancelled { .. } | Error::LimitExceeded { .. } => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, value)
+    }
+}
+
+/// Why an operation was stopped from inside a reader or a writer, carried by an [`io::Error`]
+#[derive(Debug)]
+pub(crate) enum Interrupt {
+    Cancelled,
+    LimitExceeded(Limit),
+}
+
+impl Interrupt {
+    /// Find the interrupt `err` comes from, even when it was wrapped by another error
+    fn find(err: &io::Error) -> Option<&Interrupt> {
+        let mut current = err
+            .get_ref()
+            .map(|err| err as &(dyn std::error::Error + 'static));
+        while let Some(err) = current {
+            if let Some(interrupt) = err.downcast_ref::<Interrupt>() {
+                return Some(interrupt);
+            }
+            // `io::Error` hides its payload from `source`
+            current = match err.downcast_ref::<io::Error>() {
+                Some(err) => err
+                    .get_ref()
+                    .map(|err| err as &(dyn std::error::Error + 'static)),
+                None => err.source(),
+            };
+        }
+        None
+    }
+}
+
+impl fmt::Display for Interrupt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interrupt::Cancelled => write!(f, "the operation was cancelled"),
+            Interrupt::LimitExceeded(limit) => write!(f, "exceeded {limit}"),
+        }
+    }
+}
+
+impl std::error::Error for Interrupt {}
+
+impl From<Interrupt> for io::Error {
+    fn from(value: Interrupt) -> Self {
+        io::Error::other(value)
+    }
+}
+
+/// Attach a path and a [`Phase`] to I/O results
+pub(crate) trait ResultExt<T> {
+    fn context(self, path: impl AsRef<Path>, phase: Phase) -> Result<T, Error>;
+}
+
+impl<T> ResultExt<T> for io::Result<T> {
+    fn context(self, path: impl AsRef<Path>, phase: Phase) -> Result<T, Error> {
+        self.map_err(|err| Error::io(path.as_ref(), phase, err))
+    }
+}
@@ -0,0 +1,262 @@
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use std::io::{self, Read, Write};
+
+use crate::{
+    parallel::{ParallelGzDecoder, ParallelGzEncoder},
+    CompressionLevel, Error,
+};
+
+/// The container and compression format of an archive
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Format {
+    /// Gzip compressed tar archive (`.tar.gz`)
+    #[default]
+    Gzip,
+    /// Uncompressed tar archive (`.tar`)
+    Tar,
+    /// Zip archive (`.zip`), entries are compressed with deflate or stored
+    Zip,
+}
+
+impl Format {
+    /// Get the file extension usually used for archives of this format
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::Format;
+    ///
+    /// assert_eq!(Format::Gzip.extension(), "tar.gz");
+    /// ```
+    #[must_use]
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Gzip => "tar.gz",
+            Format::Tar => "tar",
+            Format::Zip => "zip",
+        }
+    }
+
+    /// Detect the format of an archive from its first bytes
+    ///
+    /// Plain tar archives are only recognized when the whole first header block (512 bytes) is given
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::Format;
+    ///
+    /// assert_eq!(Format::detect(&[0x1f, 0x8b, 0x08, 0x00]), Some(Format::Gzip));
+    /// assert_eq!(Format::detect(b"plain text"), None);
+    /// ```
+    ///
+    /// A zip archive spanned in a single segment starts with a marker, it is skipped when extracting:
+    ///
+    /// ```
+    /// use comprexor::{CompressionLevel, Compressor, Extractor, Format};
+    /// use std::path::Path;
+    ///
+    /// let archive = Compressor::in_memory()
+    ///     .format(Format::Zip)
+    ///     .add_bytes("file.txt", "content", 0o644)
+    ///     .build()
+    ///     .unwrap()
+    ///     .compress_to_vec(CompressionLevel::Default)
+    ///     .unwrap();
+    /// let spanned = [b"PK\x07\x08".as_slice(), &archive].concat();
+    /// assert_eq!(Format::detect(&spanned), Some(Format::Zip));
+    ///
+    /// let files = Extractor::in_memory()
+    ///     .extract_to_map_from_reader(spanned.as_slice())
+    ///     .unwrap();
+    /// assert_eq!(files[Path::new("file.txt")], b"content");
+    /// ```
+    #[must_use]
+    pub fn detect(header: &[u8]) -> Option<Format> {
+        if header.starts_with(&GZIP_MAGIC) {
+            Some(Format::Gzip)
+        } else if ZIP_MAGICS.iter().any(|magic| header.starts_with(magic)) {
+            Some(Format::Zip)
+        } else if is_tar_header(header) {
+            Some(Format::Tar)
+        } else {
+            None
+        }
+    }
+
+    /// Check that `level` is supported by this format
+    pub(crate) fn check_level(self, level: &CompressionLevel) -> Result<(), Error> {
+        match self {
+            Format::Gzip | Format::Zip => Compression::try_from(level).map(|_| ()),
+            Format::Tar => Ok(()),
+        }
+    }
+
+    /// Get the codec the tar archive is compressed with, `None` for formats that are not tar based
+    pub(crate) fn codec(self) -> Option<Codec> {
+        match self {
+            Format::Gzip => Some(Codec::Gzip),
+            Format::Tar => Some(Codec::Plain),
+            Format::Zip => None,
+        }
+    }
+}
+
+/// Number of bytes needed by [`Format::detect`] to recognize every format
+pub(crate) const DETECT_LEN: usize = 512;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Local file header, end of central directory for empty archives and spanned archive markers
+const ZIP_MAGICS: [&[u8]; 3] = [b"PK\x03\x04", b"PK\x05\x06", b"PK\x07\x08"];
+
+/// Formats that can be recognized but not decoded by this crate
+const UNSUPPORTED_MAGICS: [(&[u8], &str); 3] = [
+    (&[0x28, 0xb5, 0x2f, 0xfd], "zstd"),
+    (&[0xfd, b'7', b'z', b'X', b'Z', 0x00], "xz"),
+    (b"BZh", "bzip2"),
+];
+
+/// Get the name of a known archive format that can not be decoded
+pub(crate) fn detect_unsupported(header: &[u8]) -> Option<&'static str> {
+    UNSUPPORTED_MAGICS
+        .iter()
+        .find(|(magic, _)| header.starts_with(magic))
+        .map(|(_, name)| *name)
+}
+
+/// Check the `ustar` magic or, for old v7 archives, the header checksum
+fn is_tar_header(header: &[u8]) -> bool {
+    let Some(block) = header.get(..512) else {
+        return false;
+    };
+    if &block[257..262] == b"ustar" {
+        return true;
+    }
+
+    let stored = std::str::from_utf8(&block[148..156])
+        .ok()
+        .map(|field| field.trim_matches(|c: char| c == '\0' || c == ' '))
+        .and_then(|field| u32::from_str_radix(field, 8).ok());
+    let computed: u32 = block
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| {
+            if (148..156).contains(&i) {
+                u32::from(b' ')
+            } else {
+                u32::from(*byte)
+            }
+        })
+        .sum();
+
+    // An all zero block is an end-of-archive marker, not a header
+    block.iter().any(|byte| *byte != 0) && stored == Some(computed)
+}
+
+/// The compression applied on top of a tar archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Codec {
+    Gzip,
+    /// The tar archive is not compressed
+    Plain,
+}
+
+/// Compresses everything written to it with a [`Codec`]
+pub(crate) enum Encoder<W: Write> {
+    Gzip(GzEncoder<W>),
+    /// Gzip compressed on several threads
+    ParallelGzip(ParallelGzEncoder<W>),
+    Plain(W),
+}
+
+impl<W: Write> Encoder<W> {
+    pub(crate) fn new(
+        codec: Codec,
+        writer: W,
+        level: &CompressionLevel,
+        threads: usize,
+    ) -> Result<Self, Error> {
+        match codec {
+            Codec::Gzip if threads > 1 => Ok(Encoder::ParallelGzip(ParallelGzEncoder::new(
+                writer,
+                Compression::try_from(level)?,
+                threads,
+            ))),
+            Codec::Gzip => Ok(Encoder::Gzip(GzEncoder::new(
+                writer,
+                Compression::try_from(level)?,
+            ))),
+            Codec::Plain => Ok(Encoder::Plain(writer)),
+        }
+    }
+
+    /// Write the remaining compressed data and return the underlying writer
+    pub(crate) fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::ParallelGzip(encoder) => encoder.finish(),
+            Encoder::Plain(writer) => Ok(writer),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::ParallelGzip(encoder) => encoder.write(buf),
+            Encoder::Plain(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::ParallelGzip(encoder) => encoder.flush(),
+            Encoder::Plain(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Decompresses everything read from it with a [`Codec`]
+pub(crate) enum Decoder<R: Read> {
+    /// Gzip with any number of members, like `gzip -d` reads it
+    Gzip(Box<MultiGzDecoder<R>>),
+    /// BGZF gzip decompressed on several threads
+    ParallelGzip(Box<ParallelGzDecoder<R>>),
+    Plain(R),
+}
+
+impl<R: Read> Decoder<R> {
+    pub(crate) fn new(codec: Codec, reader: R) -> Self {
+        match codec {
+            Codec::Gzip => Decoder::Gzip(Box::new(MultiGzDecoder::new(reader))),
+            Codec::Plain => Decoder::Plain(reader),
+        }
+    }
+
+    /// Decompress a BGZF gzip file on `threads` threads
+    pub(crate) fn parallel_gzip(reader: R, threads: usize) -> Self {
+        Decoder::ParallelGzip(Box::new(ParallelGzDecoder::new(reader, threads)))
+    }
+
+    pub(crate) fn get_ref(&self) -> &R {
+        match self {
+            Decoder::Gzip(decoder) => decoder.get_ref(),
+            Decoder::ParallelGzip(decoder) => decoder.get_ref(),
+            Decoder::Plain(reader) => reader,
+        }
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Decoder::Gzip(decoder) => decoder.read(buf),
+            Decoder::ParallelGzip(decoder) => decoder.read(buf),
+            Decoder::Plain(reader) => reader.read(buf),
+        }
+    }
+}
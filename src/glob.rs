@@ -0,0 +1,233 @@
+use crate::Error;
+
+/// A glob pattern matched against paths relative to an input directory
+///
+/// The syntax is the one of `.gitignore` files: `*` and `?` match inside a path component,
+/// `[a-z]` and `[!a-z]` match character classes, `**` matches any number of components
+/// and `\` escapes the next character. A pattern without `/` matches the name of
+/// entries at any depth, a pattern ending with `/` only matches directories.
+#[derive(Debug, Clone)]
+pub(crate) struct Glob {
+    parts: Vec<Part>,
+    dir_only: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    /// `**`, any number of path components
+    AnyDepth,
+    /// A single path component
+    Name(Vec<Token>),
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    Char(char),
+    /// `?`
+    AnyChar,
+    /// `*`
+    Star,
+    /// `[...]`, a list of inclusive ranges
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Char(expected) => c == *expected,
+            Token::AnyChar => true,
+            Token::Star => false,
+            Token::Class { negated, ranges } => {
+                ranges
+                    .iter()
+                    .any(|(start, end)| (*start..=*end).contains(&c))
+                    != *negated
+            }
+        }
+    }
+}
+
+impl Glob {
+    pub(crate) fn new(pattern: &str) -> Result<Self, String> {
+        let (pattern, dir_only) = match pattern.strip_suffix('/') {
+            Some(pattern) => (pattern, true),
+            None => (pattern, false),
+        };
+        if pattern.is_empty() {
+            return Err("the pattern is empty".to_string());
+        }
+
+        let mut parts = Vec::new();
+        // Like `.gitignore`, only patterns with a `/` are relative to the directory
+        if !pattern.contains('/') {
+            parts.push(Part::AnyDepth);
+        }
+        for part in pattern.split('/').filter(|part| !part.is_empty()) {
+            if part == "**" {
+                parts.push(Part::AnyDepth);
+            } else {
+                parts.push(Part::Name(parse_name(part)?));
+            }
+        }
+
+        Ok(Self { parts, dir_only })
+    }
+
+    /// Check if the entry at `path`, given by its components, matches the pattern
+    pub(crate) fn matches(&self, path: &[String], is_dir: bool) -> bool {
+        (is_dir || !self.dir_only) && match_parts(&self.parts, path)
+    }
+}
+
+/// Compile the patterns given to `option`
+pub(crate) fn compile(option: &'static str, patterns: &[String]) -> Result<Vec<Glob>, Error> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Glob::new(pattern).map_err(|reason| Error::InvalidOption {
+                option,
+                reason: format!("`{pattern}`: {reason}"),
+            })
+        })
+        .collect()
+}
+
+fn parse_name(part: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = part.chars();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '\\' => Token::Char(chars.next().ok_or("the pattern ends with `\\`")?),
+            '?' => Token::AnyChar,
+            // `**` inside a component is the same as `*`
+            '*' if matches!(tokens.last(), Some(Token::Star)) => continue,
+            '*' => Token::Star,
+            '[' => parse_class(&mut chars)?,
+            c => Token::Char(c),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Parse a character class, after its opening `[`
+fn parse_class(chars: &mut std::str::Chars<'_>) -> Result<Token, String> {
+    let mut negated = false;
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let mut c = chars.next().ok_or("a `[` is not closed")?;
+        match c {
+            '!' | '^' if first && !negated => {
+                negated = true;
+                continue;
+            }
+            // A `]` right after the opening bracket is part of the class
+            ']' if !first => break,
+            '\\' => c = chars.next().ok_or("a `[` is not closed")?,
+            _ => {}
+        }
+        first = false;
+
+        let mut ahead = chars.clone();
+        match (ahead.next(), ahead.next()) {
+            (Some('-'), Some(end)) if end != ']' => {
+                *chars = ahead;
+                if end < c {
+                    return Err(format!("the range `{c}-{end}` is reversed"));
+                }
+                ranges.push((c, end));
+            }
+            _ => ranges.push((c, c)),
+        }
+    }
+    Ok(Token::Class { negated, ranges })
+}
+
+fn match_parts(parts: &[Part], path: &[String]) -> bool {
+    match parts.split_first() {
+        None => path.is_empty(),
+        Some((Part::AnyDepth, rest)) => {
+            (0..=path.len()).any(|skip| match_parts(rest, &path[skip..]))
+        }
+        Some((Part::Name(tokens), rest)) => path
+            .split_first()
+            .is_some_and(|(name, path)| match_name(tokens, name) && match_parts(rest, path)),
+    }
+}
+
+/// Match a path component, backtracking to the last `*` on a mismatch
+fn match_name(tokens: &[Token], name: &str) -> bool {
+    let name: Vec<char> = name.chars().collect();
+    let (mut t, mut n) = (0, 0);
+    let mut star = None;
+    while n < name.len() {
+        match tokens.get(t) {
+            Some(Token::Star) => {
+                star = Some((t + 1, n));
+                t += 1;
+            }
+            Some(token) if token.matches(name[n]) => {
+                t += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_t, star_n)) => {
+                    t = star_t;
+                    n = star_n + 1;
+                    star = Some((star_t, n));
+                }
+                None => return false,
+            },
+        }
+    }
+    tokens[t..].iter().all(|token| matches!(token, Token::Star))
+}
+
+/// The rules of the `.gitignore` and `.ignore` files of a directory
+#[derive(Debug, Default)]
+pub(crate) struct IgnoreRules {
+    /// The patterns in file order, with whether they start with `!`
+    rules: Vec<(Glob, bool)>,
+}
+
+impl IgnoreRules {
+    /// Add the rules of an ignore file, lines that are not valid patterns are left out
+    pub(crate) fn add(&mut self, content: &str) {
+        for line in content.lines() {
+            let mut line = line.strip_suffix('\r').unwrap_or(line);
+            // Trailing spaces are ignored unless they are escaped
+            while line.ends_with(' ') && !line.ends_with("\\ ") {
+                line = &line[..line.len() - 1];
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (line, negated) = match line.strip_prefix('!') {
+                Some(line) => (line, true),
+                None => (line, false),
+            };
+            if let Ok(glob) = Glob::new(line) {
+                self.rules.push((glob, negated));
+            }
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Check if `path`, relative to the directory of the rules, is ignored
+    ///
+    /// The last matching rule wins, `None` is returned when no rule matches
+    pub(crate) fn is_ignored(&self, path: &[String], is_dir: bool) -> Option<bool> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|(glob, _)| glob.matches(path, is_dir))
+            .map(|(_, negated)| !negated)
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    fmt, io,
+    path::{Component, Path, PathBuf},
+};
+
+use tar::EntryType;
+
+use crate::strip_path;
+
+/// An archive entry left out by a hardened extraction, see [`Extractor::hardened`](crate::Extractor::hardened)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RejectedEntry {
+    path: PathBuf,
+    reason: RejectReason,
+}
+
+impl RejectedEntry {
+    pub(crate) fn new(path: PathBuf, reason: RejectReason) -> Self {
+        Self { path, reason }
+    }
+
+    /// Get the path of the entry, as stored in the archive
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get why the entry was left out
+    #[must_use]
+    pub fn reason(&self) -> RejectReason {
+        self.reason
+    }
+}
+
+/// Why a hardened extraction left an entry out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum RejectReason {
+    /// The path of the entry is absolute
+    AbsolutePath,
+    /// The path of the entry contains a `..` component
+    ParentDirectory,
+    /// The entry is a symlink or a hard link to something outside of the output directory
+    LinkOutsideDestination,
+    /// The entry is a device node or a named pipe
+    SpecialFile,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::AbsolutePath => write!(f, "the path is absolute"),
+            RejectReason::ParentDirectory => write!(f, "the path contains `..`"),
+            RejectReason::LinkOutsideDestination => {
+                write!(f, "the link points outside of the output directory")
+            }
+            RejectReason::SpecialFile => write!(f, "the entry is a device node or a named pipe"),
+        }
+    }
+}
+
+/// Check the path of an entry, it must be relative and stay inside of the output directory
+pub(crate) fn check_path(path: &Path) -> Option<RejectReason> {
+    path.components().find_map(|component| match component {
+        Component::Prefix(_) | Component::RootDir => Some(RejectReason::AbsolutePath),
+        Component::ParentDir => Some(RejectReason::ParentDirectory),
+        Component::CurDir | Component::Normal(_) => None,
+    })
+}
+
+/// Check the raw name of a zip entry, zip archives made on Windows may use `\` and drive letters
+pub(crate) fn check_zip_name(name: &[u8]) -> Option<RejectReason> {
+    let drive = name.first().is_some_and(u8::is_ascii_alphabetic) && name.get(1) == Some(&b':');
+    if drive || name.starts_with(b"/") || name.starts_with(b"\\") {
+        return Some(RejectReason::AbsolutePath);
+    }
+    name.split(|&byte| byte == b'/' || byte == b'\\')
+        .any(|part| part == b"..")
+        .then_some(RejectReason::ParentDirectory)
+}
+
+/// Check a tar entry before it is unpacked at `path` into `output`, which must be canonical
+///
+/// `path` is the path of the entry once the `strip` leading components were removed,
+/// the same components are removed from the target of a hard link
+pub(crate) fn check_tar_entry<R: io::Read>(
+    entry: &tar::Entry<'_, R>,
+    path: &Path,
+    output: &Path,
+    strip: usize,
+) -> io::Result<Option<RejectReason>> {
+    if let Some(reason) = check_path(path) {
+        return Ok(Some(reason));
+    }
+
+    let reason = match entry.header().entry_type() {
+        EntryType::Symlink => {
+            let target = entry.link_name()?.unwrap_or_default();
+            let link_dir = path.parent().unwrap_or(Path::new(""));
+            (!link_stays_inside(output, link_dir, &target))
+                .then_some(RejectReason::LinkOutsideDestination)
+        }
+        // Hard link targets are relative to the root of the archive, a target stripped to nothing is outside of it
+        EntryType::Link => {
+            let target = entry.link_name()?.unwrap_or_default();
+            let inside = strip_path(&target, strip)
+                .is_some_and(|target| link_stays_inside(output, Path::new(""), &target));
+            (!inside).then_some(RejectReason::LinkOutsideDestination)
+        }
+        EntryType::Char | EntryType::Block | EntryType::Fifo => Some(RejectReason::SpecialFile),
+        _ => None,
+    };
+    Ok(reason)
+}
+
+/// Check that a link in `link_dir`, relative to `root`, pointing to `target` stays inside `root`
+///
+/// The symlinks already in `root` are followed. A `..` is only accepted after an existing
+/// directory or a missing parent of the link, which is created as a directory when the
+/// link is unpacked. Other missing paths or files could be replaced by a symlink later on.
+pub(crate) fn link_stays_inside(root: &Path, link_dir: &Path, target: &Path) -> bool {
+    let mut path = root.to_path_buf();
+    if !link_dir
+        .components()
+        .all(|component| step(root, &mut path, component, Path::new("")))
+    {
+        return false;
+    }
+    let link_dir = path.clone();
+    target
+        .components()
+        .all(|component| step(root, &mut path, component, &link_dir))
+}
+
+/// Apply `component` to `path`, returns `false` if it goes outside of `root`
+fn step(root: &Path, path: &mut PathBuf, component: Component<'_>, link_dir: &Path) -> bool {
+    match component {
+        Component::CurDir => true,
+        Component::ParentDir => {
+            let is_dir = match path.symlink_metadata() {
+                Ok(meta) => meta.is_dir(),
+                Err(_) => link_dir.starts_with(&*path),
+            };
+            if *path == root || !is_dir {
+                return false;
+            }
+            path.pop();
+            true
+        }
+        Component::Normal(name) => {
+            path.push(name);
+            if path.symlink_metadata().is_ok_and(|meta| meta.is_symlink()) {
+                match path.canonicalize() {
+                    Ok(real) => *path = real,
+                    Err(_) => return false,
+                }
+            }
+            path.starts_with(root)
+        }
+        Component::Prefix(_) | Component::RootDir => false,
+    }
+}
@@ -1,23 +1,60 @@
-use flate2::{write::GzEncoder, Compression};
+use flate2::Compression;
 use humansize::{make_format, DECIMAL};
 use std::{
-    collections::hash_map::DefaultHasher,
-    fmt,
-    fs::File,
-    hash::Hasher,
-    io::{copy, BufReader, Seek, SeekFrom},
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    ffi::OsStr,
+    fs::Metadata,
+    io::{copy, BufReader, BufWriter, Chain, Cursor, Read, Write},
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
 };
-use tar::Archive;
-use tempfile::NamedTempFile;
+use tar::{Archive, EntryType, HeaderMode};
+
+mod builder;
+mod cancel;
+mod counter;
+mod error;
+mod format;
+mod glob;
+mod harden;
+mod limits;
+mod list;
+mod memory;
+mod overwrite;
+mod parallel;
+mod progress;
+mod select;
+mod walk;
+mod zip;
+
+pub use builder::CompressorBuilder;
+pub use cancel::CancellationToken;
+use counter::{CountingReader, CountingWriter};
+use error::ResultExt;
+pub use error::{Error, Phase};
+pub use format::Format;
+use format::{Codec, Decoder, Encoder, DETECT_LEN};
+pub use harden::{RejectReason, RejectedEntry};
+pub use limits::{Limit, Limits};
+pub use list::{ArchiveEntry, EntryKind, Listing};
+use memory::MemoryFile;
+pub use overwrite::{Conflict, OverwritePolicy, Resolution};
+pub use progress::Progress;
+use progress::{Count, ProgressCallback, Tracked, Tracker};
+use select::Selection;
+use walk::{normalize, Input, Walk};
+use zip::{ZipEntry, ZipReader, ZipWriter};
 
 /// The compression level to use when compressing files (0-9)
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Default, PartialEq, PartialOrd, Hash)]
 pub enum CompressionLevel {
     /// No compression (0)
     None,
     /// Fast compression (1)
     Fast,
     /// Default compression (6)
+    #[default]
     Default,
     /// Maximum compression (9)
     Maximum,
@@ -58,7 +95,7 @@ impl From<CompressionLevel> for u32 {
 }
 
 impl TryFrom<CompressionLevel> for Compression {
-    type Error = String;
+    type Error = Error;
 
     fn try_from(value: CompressionLevel) -> Result<Self, Self::Error> {
         use CompressionLevel::{Custom, Default, Fast, Maximum, None};
@@ -69,9 +106,7 @@ impl TryFrom<CompressionLevel> for Compression {
             Maximum => Ok(Compression::best()),
             Custom(level) => {
                 if level > 9 {
-                    Err(format!(
-                        "Invalid compression level: {level}, must be between 0 and 9"
-                    ))
+                    Err(Error::InvalidLevel(level))
                 } else {
                     Ok(Compression::new(level))
                 }
@@ -81,7 +116,7 @@ impl TryFrom<CompressionLevel> for Compression {
 }
 
 impl TryFrom<&CompressionLevel> for Compression {
-    type Error = String;
+    type Error = Error;
 
     fn try_from(value: &CompressionLevel) -> Result<Self, Self::Error> {
         use CompressionLevel::{Custom, Default, Fast, Maximum, None};
@@ -92,9 +127,7 @@ impl TryFrom<&CompressionLevel> for Compression {
             Maximum => Ok(Compression::best()),
             Custom(level) => {
                 if *level > 9 {
-                    Err(format!(
-                        "Invalid compression level: {level}, must be between 0 and 9"
-                    ))
+                    Err(Error::InvalidLevel(*level))
                 } else {
                     Ok(Compression::new(*level))
                 }
@@ -108,9 +141,50 @@ pub struct ArchiveInfo {
     input_size: u64,
     output_size: u64,
     ratio: f64,
+    format: Format,
+    rejected: Vec<RejectedEntry>,
+    conflicts: Vec<Conflict>,
 }
 
 impl ArchiveInfo {
+    /// Get the format of the archive, for extraction this is the detected format
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{Compressor, Extractor, Format};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// std::fs::write("./file.txt", "content").unwrap();
+    /// Compressor::builder("./file.txt", "./file.tar")
+    ///     .format(Format::Tar)
+    ///     .build()
+    ///     .unwrap()
+    ///     .run()
+    ///     .unwrap();
+    ///
+    /// let archive_data = Extractor::new("./file.tar", "./output").extract().unwrap();
+    /// assert_eq!(archive_data.format(), Format::Tar);
+    /// ```
+    #[must_use]
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Get the entries left out by a hardened extraction, see [`Extractor::hardened`]
+    #[must_use]
+    pub fn rejected_entries(&self) -> &[RejectedEntry] {
+        &self.rejected
+    }
+
+    /// Get the paths that already existed when an extraction wanted to write them, with what was
+    /// done about each of them, see [`Extractor::overwrite`]
+    #[must_use]
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
     /// Get the input size without formatting
     #[must_use]
     pub fn input_size(&self) -> u64 {
@@ -122,15 +196,17 @@ impl ArchiveInfo {
     /// # Example
     ///
     /// ```
-    /// use comprexor::ArchiveInfo;
+    /// use comprexor::{CompressionLevel, Compressor};
     ///
-    /// let archive_data = ArchiveInfo {
-    ///    input_size: 1000,
-    ///    output_size: 1000,
-    ///    ratio: 1.0,
-    /// };
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// std::fs::write("./file.txt", [b'a'; 1000]).unwrap();
+    ///
+    /// let compressor = Compressor::new("./file.txt", "./file.tar.gz");
+    /// let archive_data = compressor.compress(CompressionLevel::Default).unwrap();
     ///
-    /// assert_eq!(archive_data.input_size_formatted(), "1.0 kB");
+    /// // The input size is the size of the generated tar
+    /// assert_eq!(archive_data.input_size_formatted(), "2.56 kB");
     /// ```
     #[must_use]
     pub fn input_size_formatted(&self) -> String {
@@ -149,15 +225,18 @@ impl ArchiveInfo {
     /// # Example
     ///
     /// ```
-    /// use comprexor::ArchiveInfo;
+    /// use comprexor::{CompressionLevel, Compressor, Extractor};
     ///
-    /// let archive_data = ArchiveInfo {
-    ///   input_size: 1000,
-    ///   output_size: 1000,
-    ///   ratio: 1.0,
-    /// };
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// std::fs::write("./file.txt", [b'a'; 1000]).unwrap();
+    /// Compressor::new("./file.txt", "./file.tar.gz").compress(CompressionLevel::Default).unwrap();
+    ///
+    /// let extractor = Extractor::new("./file.tar.gz", "./output");
+    /// let archive_data = extractor.extract().unwrap();
     ///
-    /// assert_eq!(archive_data.output_size_formatted(), "1.0 kB");
+    /// // The output size is the size of the decompressed tar
+    /// assert_eq!(archive_data.output_size_formatted(), "2.56 kB");
     /// ```
     #[must_use]
     pub fn output_size_formatted(&self) -> String {
@@ -176,16 +255,17 @@ impl ArchiveInfo {
     /// # Example
     ///
     /// ```
-    /// use comprexor::ArchiveInfo;
+    /// use comprexor::{CompressionLevel, Compressor};
     ///
-    /// let archive_data = ArchiveInfo {
-    ///     input_size: 1000,
-    ///     output_size: 1000,
-    ///     ratio: 1.0,
-    /// };
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// std::fs::write("./file.txt", [b'a'; 1000]).unwrap();
     ///
-    /// assert_eq!(archive_data.ratio_formatted(5), "1.00000");
-    /// assert_eq!(archive_data.ratio_formatted(2), "1.00");
+    /// let compressor = Compressor::new("./file.txt", "./file.tar.gz");
+    /// let archive_data = compressor.compress(CompressionLevel::None).unwrap();
+    ///
+    /// assert_eq!(archive_data.ratio_formatted(2).len(), 4);
+    /// assert_eq!(archive_data.ratio_formatted(5), format!("{:.5}", archive_data.ratio()));
     /// ```
     #[must_use]
     pub fn ratio_formatted(&self, num_decimals: u8) -> String {
@@ -197,33 +277,43 @@ impl ArchiveInfo {
     }
 }
 
-trait ArchiveExt {
-    fn gen_hashed_name<T>(input: &T) -> String
-    where
-        T: std::hash::Hash + fmt::Display,
-    {
-        let mut hasher = DefaultHasher::new();
-        input.hash(&mut hasher);
-        format!("{}-{}", hasher.finish(), "tar-gen.tar")
-    }
-}
-
 #[derive(Debug, Clone, PartialEq, PartialOrd, Hash)]
-pub struct Compressor<'a> {
-    input: &'a str,
-    output: &'a str,
+pub struct Compressor {
+    /// The input given to `new` followed by the ones added to the builder
+    inputs: Vec<Input>,
+    output: PathBuf,
+    format: Format,
+    level: CompressionLevel,
+    follow_symlinks: bool,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    respect_ignore_files: bool,
+    contents_at_root: bool,
+    reproducible: bool,
+    /// Files added from memory, stored after the inputs
+    files: Vec<MemoryFile>,
+    threads: usize,
+    progress: Option<ProgressCallback>,
+    cancel: Option<CancellationToken>,
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Hash)]
-pub struct Extractor<'a> {
-    input: &'a str,
-    output: &'a str,
+pub struct Extractor {
+    input: PathBuf,
+    output: PathBuf,
+    format: Option<Format>,
+    progress: Option<ProgressCallback>,
+    cancel: Option<CancellationToken>,
+    hardened: bool,
+    limits: Limits,
+    include: Vec<String>,
+    include_paths: Vec<PathBuf>,
+    strip_components: usize,
+    overwrite: OverwritePolicy,
+    threads: usize,
 }
 
-impl<'a> ArchiveExt for Compressor<'a> {}
-impl<'a> ArchiveExt for Extractor<'a> {}
-
-impl<'a> Extractor<'a> {
+impl Extractor {
     #[must_use]
     /// Create a new extractor with the given input and output
     ///
@@ -231,167 +321,2167 @@ impl<'a> Extractor<'a> {
     /// ```
     /// use comprexor::Extractor;
     ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::write("./file.txt", "content").unwrap();
+    /// # comprexor::Compressor::new("./file.txt", "./compacted-archive.tar.gz").compress(comprexor::CompressionLevel::Default).unwrap();
     /// let extractor = Extractor::new("./compacted-archive.tar.gz", "./output-folder-or-file");
     /// extractor.extract().unwrap();
     /// ```
-    pub fn new(input: &'a str, output: &'a str) -> Extractor<'a> {
-        Self { input, output }
+    pub fn new<I: AsRef<Path>, O: AsRef<Path>>(input: I, output: O) -> Extractor {
+        Self {
+            input: input.as_ref().to_path_buf(),
+            output: output.as_ref().to_path_buf(),
+            format: None,
+            progress: None,
+            cancel: None,
+            hardened: false,
+            limits: Limits::default(),
+            include: Vec::new(),
+            include_paths: Vec::new(),
+            strip_components: 0,
+            overwrite: OverwritePolicy::Overwrite,
+            threads: 1,
+        }
     }
 
-    /// Decompress the input file to the output file
+    /// Create an extractor without input or output paths, to read archives from memory with
+    /// [`Extractor::extract_to_map_from_reader`] or [`Extractor::list_from_reader`]
     ///
     /// # Example
     ///
     /// ```
-    /// use comprexor::Extractor;
+    /// use comprexor::{CompressionLevel, Compressor, Extractor};
+    /// use std::path::Path;
     ///
-    /// let extractor = Extractor::new("./compacted-archive.tar.gz", "./output-folder-or-file");
-    /// extractor.extract().unwrap();
+    /// let archive = Compressor::in_memory()
+    ///     .add_bytes("file.txt", "content", 0o644)
+    ///     .build()
+    ///     .unwrap()
+    ///     .compress_to_vec(CompressionLevel::Default)
+    ///     .unwrap();
+    ///
+    /// let listing = Extractor::in_memory()
+    ///     .list_from_reader(archive.as_slice())
+    ///     .unwrap();
+    /// assert_eq!(listing.entries()[0].path(), Path::new("file.txt"));
     /// ```
+    #[must_use]
+    pub fn in_memory() -> Extractor {
+        Self::new("", "")
+    }
+
+    /// Set the format of the archive to extract
     ///
-    /// # Errors
+    /// By default the format is detected from the first bytes of the input, see [`Format::detect`]
     ///
-    /// This function will return an error if the input file is not a valid gzip file or something goes wrong while decompressing
-    pub fn extract(&self) -> Result<ArchiveInfo, std::io::Error> {
-        let archive_data = self.extract_internal()?;
-        Ok(archive_data)
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{Extractor, Format};
+    ///
+    /// let extractor = Extractor::new("./compacted-archive.tar.gz", "./output").with_format(Format::Gzip);
+    /// ```
+    #[must_use]
+    pub fn with_format(mut self, format: Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Call `callback` with the [`Progress`] of the extraction as the archive is read and unpacked
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CompressionLevel, Compressor, Extractor};
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::create_dir("./folder").unwrap();
+    /// # std::fs::write("./folder/file.txt", [b'a'; 100_000]).unwrap();
+    /// # Compressor::new("./folder", "./archive.tar.gz").compress(CompressionLevel::Default).unwrap();
+    /// let last = Arc::new(Mutex::new(None));
+    /// let extractor = Extractor::new("./archive.tar.gz", "./output").on_progress({
+    ///     let last = last.clone();
+    ///     move |progress| *last.lock().unwrap() = Some(progress.clone())
+    /// });
+    /// let archive_data = extractor.extract().unwrap();
+    ///
+    /// let progress = last.lock().unwrap().take().unwrap();
+    /// assert_eq!(progress.entries_processed(), 2);
+    /// assert_eq!(progress.bytes_read(), archive_data.input_size());
+    /// assert_eq!(progress.total_bytes(), Some(archive_data.input_size()));
+    /// assert_eq!(progress.bytes_written(), archive_data.output_size());
+    /// ```
+    #[must_use]
+    pub fn on_progress<F: Fn(&Progress) + Send + Sync + 'static>(mut self, callback: F) -> Self {
+        self.progress = Some(ProgressCallback::new(callback));
+        self
     }
 
-    fn extract_internal(&self) -> Result<ArchiveInfo, std::io::Error> {
-        let input_file = BufReader::new(std::fs::File::open(self.input)?);
-        let input_size = std::fs::metadata(self.input)?.len();
-        let mut tmpfile = tempfile::tempfile()?;
+    /// Stop the extraction when `token` is cancelled
+    ///
+    /// The token is checked before every entry and every chunk of data. What the
+    /// extraction created is then removed and [`Error::Cancelled`] is returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CancellationToken, CompressionLevel, Compressor, Error, Extractor};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::create_dir("./folder").unwrap();
+    /// # for i in 0..10 {
+    /// #     std::fs::write(format!("./folder/file-{i}.txt"), [b'a'; 100_000]).unwrap();
+    /// # }
+    /// # Compressor::new("./folder", "./archive.tar.gz").compress(CompressionLevel::Default).unwrap();
+    /// let token = CancellationToken::new();
+    /// let extractor = Extractor::new("./archive.tar.gz", "./output")
+    ///     .cancellation_token(token.clone())
+    ///     .on_progress(move |progress| {
+    ///         // Give up halfway through
+    ///         if progress.entries_processed() == 5 {
+    ///             token.cancel();
+    ///         }
+    ///     });
+    ///
+    /// assert!(matches!(extractor.extract(), Err(Error::Cancelled { .. })));
+    /// assert!(!std::path::Path::new("./output").exists());
+    /// ```
+    #[must_use]
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
 
-        let mut decoder = flate2::read::GzDecoder::new(input_file);
-        copy(&mut decoder, &mut tmpfile)?;
-        tmpfile.seek(SeekFrom::Start(0))?;
-        let output_size = tmpfile.metadata()?.len();
+    /// Extract untrusted archives safely, defaults to `false`
+    ///
+    /// Entries with an absolute path or a `..` component, symlinks and hard links pointing
+    /// outside of the output directory, device nodes and named pipes are left out instead
+    /// of being unpacked. They are listed in [`ArchiveInfo::rejected_entries`].
+    ///
+    /// Without it, absolute paths are unpacked relative to the output directory and
+    /// a `..` component fails the extraction with [`Error::PathTraversal`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{Extractor, RejectReason};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// // An archive with a symlink to `/etc/passwd`
+    /// let mut builder = tar::Builder::new(Vec::new());
+    /// let mut header = tar::Header::new_gnu();
+    /// header.set_entry_type(tar::EntryType::Symlink);
+    /// header.set_size(0);
+    /// builder.append_link(&mut header, "passwd", "/etc/passwd").unwrap();
+    /// let archive = builder.into_inner().unwrap();
+    ///
+    /// let archive_data = Extractor::new("", "./output")
+    ///     .hardened(true)
+    ///     .extract_from_reader(archive.as_slice())
+    ///     .unwrap();
+    ///
+    /// let rejected = &archive_data.rejected_entries()[0];
+    /// assert_eq!(rejected.path(), std::path::Path::new("passwd"));
+    /// assert_eq!(rejected.reason(), RejectReason::LinkOutsideDestination);
+    /// assert!(std::fs::symlink_metadata("./output/passwd").is_err());
+    /// ```
+    ///
+    /// Every other kind of entry left out, the target of a hard link is stripped like the paths:
+    ///
+    /// ```
+    /// use comprexor::{Extractor, RejectReason};
+    /// use tar::EntryType;
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// // The names are written as is, `tar::Header::set_path` refuses `..`
+    /// let mut builder = tar::Builder::new(Vec::new());
+    /// let mut append = |name: &str, kind: EntryType, link: &str| {
+    ///     let mut header = tar::Header::new_old();
+    ///     header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+    ///     header.as_old_mut().linkname[..link.len()].copy_from_slice(link.as_bytes());
+    ///     header.set_entry_type(kind);
+    ///     header.set_mode(0o644);
+    ///     header.set_size(0);
+    ///     header.set_cksum();
+    ///     builder.append(&header, std::io::empty()).unwrap();
+    /// };
+    /// append("top/file.txt", EntryType::Regular, "");
+    /// append("top/../../escape.txt", EntryType::Regular, "");
+    /// append("/top/absolute.txt", EntryType::Regular, "");
+    /// append("top/outside", EntryType::Link, "top/../../escape.txt");
+    /// append("top/root", EntryType::Link, "top");
+    /// append("top/inside", EntryType::Link, "top/file.txt");
+    /// append("top/pipe", EntryType::Fifo, "");
+    /// append("top/null", EntryType::Char, "");
+    /// append("top/disk", EntryType::Block, "");
+    /// let archive = builder.into_inner().unwrap();
+    ///
+    /// let archive_data = Extractor::new("", "./output")
+    ///     .hardened(true)
+    ///     .strip_components(1)
+    ///     .extract_from_reader(archive.as_slice())
+    ///     .unwrap();
+    ///
+    /// let rejected: Vec<_> = archive_data
+    ///     .rejected_entries()
+    ///     .iter()
+    ///     .map(|rejected| (rejected.path().to_str().unwrap(), rejected.reason()))
+    ///     .collect();
+    /// assert_eq!(
+    ///     rejected,
+    ///     [
+    ///         ("top/../../escape.txt", RejectReason::ParentDirectory),
+    ///         ("/top/absolute.txt", RejectReason::AbsolutePath),
+    ///         ("top/outside", RejectReason::LinkOutsideDestination),
+    ///         // `top` is stripped to nothing
+    ///         ("top/root", RejectReason::LinkOutsideDestination),
+    ///         ("top/pipe", RejectReason::SpecialFile),
+    ///         ("top/null", RejectReason::SpecialFile),
+    ///         ("top/disk", RejectReason::SpecialFile),
+    ///     ]
+    /// );
+    /// assert!(std::path::Path::new("./output/file.txt").is_file());
+    /// assert!(std::path::Path::new("./output/inside").is_file());
+    /// assert!(!std::path::Path::new("escape.txt").exists());
+    /// ```
+    #[must_use]
+    pub fn hardened(mut self, hardened: bool) -> Self {
+        self.hardened = hardened;
+        self
+    }
 
-        let mut archive = Archive::new(tmpfile);
-        archive.unpack(self.output)?;
+    /// Stop the extraction when it goes over `limits`, to protect against decompression bombs
+    ///
+    /// What the extraction created is then removed and [`Error::LimitExceeded`] is returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CompressionLevel, Compressor, Error, Extractor, Limit, Limits};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// // 10 MB of zeros compress to about 10 kB
+    /// std::fs::write("./zeros", vec![0; 10_000_000]).unwrap();
+    /// Compressor::new("./zeros", "./bomb.tar.gz").compress(CompressionLevel::Maximum).unwrap();
+    ///
+    /// let extractor = Extractor::new("./bomb.tar.gz", "./output").limits(Limits::new().max_ratio(100));
+    /// assert!(matches!(
+    ///     extractor.extract(),
+    ///     Err(Error::LimitExceeded { limit: Limit::Ratio(100), .. })
+    /// ));
+    /// assert!(!std::path::Path::new("./output").exists());
+    /// ```
+    #[must_use]
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
 
-        Ok(ArchiveInfo {
-            input_size,
-            output_size,
-            ratio: output_size as f64 / input_size as f64,
-        })
+    /// Only extract the entries matching `pattern`, can be called multiple times
+    ///
+    /// Patterns use the syntax of [`CompressorBuilder::include`] and are matched against
+    /// the paths inside the archive. Everything inside a matching directory is extracted.
+    /// An invalid pattern makes the extraction fail with [`Error::InvalidOption`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{Compressor, Extractor};
+    /// use std::path::Path;
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// std::fs::create_dir_all("./project/docs").unwrap();
+    /// std::fs::write("./project/README.md", "# Project").unwrap();
+    /// std::fs::write("./project/docs/guide.md", "# Guide").unwrap();
+    /// std::fs::write("./project/main.rs", "fn main() {}").unwrap();
+    /// Compressor::new("./project", "./project.tar.gz").run().unwrap();
+    ///
+    /// Extractor::new("./project.tar.gz", "./output")
+    ///     .include("*.md")
+    ///     .extract()
+    ///     .unwrap();
+    ///
+    /// assert!(Path::new("./output/project/README.md").exists());
+    /// assert!(Path::new("./output/project/docs/guide.md").exists());
+    /// assert!(!Path::new("./output/project/main.rs").exists());
+    /// ```
+    #[must_use]
+    pub fn include<P: Into<String>>(mut self, pattern: P) -> Self {
+        self.include.push(pattern.into());
+        self
     }
-}
 
-impl<'a> Compressor<'a> {
+    /// Only extract the entry at `path` inside the archive, with its content if it is a
+    /// directory, can be called multiple times
+    ///
+    /// Entries selected with [`Extractor::include`] are extracted as well.
     #[must_use]
-    /// Creates a new compressor with the given input and output
+    pub fn include_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.include_paths.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Remove the first `count` components from the path of each entry, like `tar --strip-components`
+    ///
+    /// Entries with no component left are skipped, hard link targets are stripped as well.
+    /// The patterns of [`Extractor::include`] are still matched against the full paths.
     ///
     /// # Example
     ///
     /// ```
-    /// use comprexor::{CompressionLevel, Compressor};
+    /// use comprexor::{Compressor, Extractor};
     ///
-    /// let compressor = Compressor::new("./folder-or-file-to-compress", "./compacted-archive.tar.gz");
-    /// compressor.compress(CompressionLevel::Maximum).unwrap();
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// std::fs::create_dir("./folder").unwrap();
+    /// std::fs::write("./folder/file.txt", "content").unwrap();
+    /// Compressor::new("./folder", "./archive.tar.gz").run().unwrap();
+    ///
+    /// Extractor::new("./archive.tar.gz", "./output")
+    ///     .strip_components(1)
+    ///     .extract()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(std::fs::read_to_string("./output/file.txt").unwrap(), "content");
     /// ```
-    pub fn new(input: &'a str, output: &'a str) -> Compressor<'a> {
-        Self { input, output }
+    #[must_use]
+    pub fn strip_components(mut self, count: usize) -> Self {
+        self.strip_components = count;
+        self
     }
 
-    /// Compress the input file or folder to the output location
+    /// Set what is done with entries that would be written where something already exists,
+    /// defaults to [`OverwritePolicy::Overwrite`]
     ///
-    /// You can choose the compression level with the `CompressionLevel` enum
+    /// What happened to each of them is reported by [`ArchiveInfo::conflicts`].
     ///
-    /// The compression level can be:
+    /// # Example
     ///
-    /// - `CompressionLevel::None`
-    /// - `CompressionLevel::Fast`
-    /// - `CompressionLevel::Default`
-    /// - `CompressionLevel::Maximum`
-    /// - `CompressionLevel::Custom` (you can specify the compression level between 0 and 9)
+    /// ```
+    /// use comprexor::{Compressor, Extractor, OverwritePolicy, Resolution};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// std::fs::write("./file.txt", "from the archive").unwrap();
+    /// Compressor::new("./file.txt", "./archive.tar.gz").run().unwrap();
+    ///
+    /// std::fs::create_dir("./output").unwrap();
+    /// std::fs::write("./output/file.txt", "already there").unwrap();
+    ///
+    /// let info = Extractor::new("./archive.tar.gz", "./output")
+    ///     .overwrite(OverwritePolicy::Skip)
+    ///     .extract()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(info.conflicts()[0].resolution(), &Resolution::Skipped);
+    /// assert_eq!(std::fs::read_to_string("./output/file.txt").unwrap(), "already there");
+    /// ```
+    ///
+    /// A directory is never replaced by a file, even with [`OverwritePolicy::Overwrite`]:
+    ///
+    /// ```
+    /// use comprexor::{Compressor, Extractor, Resolution};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// std::fs::write("./file.txt", "from the archive").unwrap();
+    /// Compressor::new("./file.txt", "./archive.tar.gz").run().unwrap();
+    ///
+    /// std::fs::create_dir_all("./output/file.txt").unwrap();
+    ///
+    /// let info = Extractor::new("./archive.tar.gz", "./output").extract().unwrap();
+    ///
+    /// assert!(info.conflicts()[0].path().ends_with("output/file.txt"));
+    /// assert_eq!(info.conflicts()[0].resolution(), &Resolution::Skipped);
+    /// assert!(std::path::Path::new("./output/file.txt").is_dir());
+    /// ```
+    #[must_use]
+    pub fn overwrite(mut self, policy: OverwritePolicy) -> Self {
+        self.overwrite = policy;
+        self
+    }
+
+    /// Decompress on `threads` threads when the archive allows it, defaults to 1
+    ///
+    /// Only gzip archives in the BGZF format, the one written by `bgzip`, are made of blocks
+    /// that can be decompressed at the same time. Other archives are decompressed on a single
+    /// thread, and 0 threads is rejected with [`Error::InvalidOption`].
     ///
     /// # Example
     ///
     /// ```
-    /// use comprexor::{CompressionLevel, Compressor};
+    /// use comprexor::Extractor;
     ///
-    /// let compressor = Compressor::new("./folder-or-file-to-compress", "./compacted-archive.tar.gz");
-    /// compressor.compress(CompressionLevel::Maximum).unwrap();
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # use std::io::Read;
+    /// # // A BGZF member: a gzip member with its size less one in the `BC` extra subfield
+    /// # fn bgzf_member(data: &[u8]) -> Vec<u8> {
+    /// #     let mut deflated = Vec::new();
+    /// #     flate2::read::DeflateEncoder::new(data, flate2::Compression::default())
+    /// #         .read_to_end(&mut deflated)
+    /// #         .unwrap();
+    /// #     let mut crc = flate2::Crc::new();
+    /// #     crc.update(data);
+    /// #     let size = u16::try_from(18 + deflated.len() + 8 - 1).unwrap();
+    /// #     let mut member = vec![0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 255, 6, 0, b'B', b'C', 2, 0];
+    /// #     member.extend_from_slice(&size.to_le_bytes());
+    /// #     member.extend_from_slice(&deflated);
+    /// #     member.extend_from_slice(&crc.sum().to_le_bytes());
+    /// #     member.extend_from_slice(&crc.amount().to_le_bytes());
+    /// #     member
+    /// # }
+    /// // A tar archive compressed the way `bgzip` does, in blocks of 0xff00 bytes
+    /// let mut builder = tar::Builder::new(Vec::new());
+    /// let content = "content ".repeat(100_000);
+    /// let mut header = tar::Header::new_gnu();
+    /// header.set_size(content.len() as u64);
+    /// header.set_mode(0o644);
+    /// builder.append_data(&mut header, "file.txt", content.as_bytes()).unwrap();
+    /// let tar = builder.into_inner().unwrap();
+    /// let mut archive: Vec<u8> = tar.chunks(0xff00).flat_map(bgzf_member).collect();
+    /// // The empty member ending a BGZF file
+    /// archive.extend(bgzf_member(&[]));
+    /// std::fs::write("./archive.tar.gz", &archive).unwrap();
+    ///
+    /// Extractor::new("./archive.tar.gz", "./output")
+    ///     .threads(2)
+    ///     .extract()
+    ///     .unwrap();
+    /// assert_eq!(std::fs::read_to_string("./output/file.txt").unwrap(), content);
     /// ```
     ///
-    /// # Errors
+    /// Members that are not BGZF, like the ones written by `gzip`, are decompressed on a
+    /// single thread along with everything after them:
     ///
-    /// This function will return an error if the input file is not a valid gzip file or something goes wrong while compressing
-    pub fn compress<T>(&self, level: T) -> Result<ArchiveInfo, std::io::Error>
-    where
-        T: AsRef<CompressionLevel>,
-    {
-        let archive_data = self.compress_with_tar(level.as_ref())?;
+    /// ```
+    /// use comprexor::Extractor;
+    /// use std::io::Write;
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # use std::io::Read;
+    /// # // A BGZF member: a gzip member with its size less one in the `BC` extra subfield
+    /// # fn bgzf_member(data: &[u8]) -> Vec<u8> {
+    /// #     let mut deflated = Vec::new();
+    /// #     flate2::read::DeflateEncoder::new(data, flate2::Compression::default())
+    /// #         .read_to_end(&mut deflated)
+    /// #         .unwrap();
+    /// #     let mut crc = flate2::Crc::new();
+    /// #     crc.update(data);
+    /// #     let size = u16::try_from(18 + deflated.len() + 8 - 1).unwrap();
+    /// #     let mut member = vec![0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 255, 6, 0, b'B', b'C', 2, 0];
+    /// #     member.extend_from_slice(&size.to_le_bytes());
+    /// #     member.extend_from_slice(&deflated);
+    /// #     member.extend_from_slice(&crc.sum().to_le_bytes());
+    /// #     member.extend_from_slice(&crc.amount().to_le_bytes());
+    /// #     member
+    /// # }
+    /// let mut builder = tar::Builder::new(Vec::new());
+    /// let content = "content ".repeat(100_000);
+    /// let mut header = tar::Header::new_gnu();
+    /// header.set_size(content.len() as u64);
+    /// header.set_mode(0o644);
+    /// builder.append_data(&mut header, "file.txt", content.as_bytes()).unwrap();
+    /// let tar = builder.into_inner().unwrap();
+    /// let (start, end) = tar.split_at(0xff00 * 4);
+    ///
+    /// // BGZF members, then a gzip member with a file name in its header
+    /// let mut archive: Vec<u8> = start.chunks(0xff00).flat_map(bgzf_member).collect();
+    /// let mut encoder = flate2::GzBuilder::new()
+    ///     .filename("end.tar")
+    ///     .write(&mut archive, flate2::Compression::default());
+    /// encoder.write_all(end).unwrap();
+    /// encoder.finish().unwrap();
+    /// archive.extend(bgzf_member(&[]));
+    /// std::fs::write("./archive.tar.gz", &archive).unwrap();
+    ///
+    /// Extractor::new("./archive.tar.gz", "./output")
+    ///     .threads(2)
+    ///     .extract()
+    ///     .unwrap();
+    /// assert_eq!(std::fs::read_to_string("./output/file.txt").unwrap(), content);
+    /// ```
+    ///
+    /// A truncated archive is reported on the archive:
+    ///
+    /// ```
+    /// use comprexor::{Error, Extractor};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # use std::io::Read;
+    /// # // A BGZF member: a gzip member with its size less one in the `BC` extra subfield
+    /// # fn bgzf_member(data: &[u8]) -> Vec<u8> {
+    /// #     let mut deflated = Vec::new();
+    /// #     flate2::read::DeflateEncoder::new(data, flate2::Compression::default())
+    /// #         .read_to_end(&mut deflated)
+    /// #         .unwrap();
+    /// #     let mut crc = flate2::Crc::new();
+    /// #     crc.update(data);
+    /// #     let size = u16::try_from(18 + deflated.len() + 8 - 1).unwrap();
+    /// #     let mut member = vec![0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 255, 6, 0, b'B', b'C', 2, 0];
+    /// #     member.extend_from_slice(&size.to_le_bytes());
+    /// #     member.extend_from_slice(&deflated);
+    /// #     member.extend_from_slice(&crc.sum().to_le_bytes());
+    /// #     member.extend_from_slice(&crc.amount().to_le_bytes());
+    /// #     member
+    /// # }
+    /// // A tar archive compressed the way `bgzip` does, in blocks of 0xff00 bytes
+    /// let mut builder = tar::Builder::new(Vec::new());
+    /// let content = "content ".repeat(100_000);
+    /// let mut header = tar::Header::new_gnu();
+    /// header.set_size(content.len() as u64);
+    /// header.set_mode(0o644);
+    /// builder.append_data(&mut header, "file.txt", content.as_bytes()).unwrap();
+    /// let tar = builder.into_inner().unwrap();
+    /// let mut archive: Vec<u8> = tar.chunks(0xff00).flat_map(bgzf_member).collect();
+    /// // The empty member ending a BGZF file
+    /// archive.extend(bgzf_member(&[]));
+    /// std::fs::write("./truncated.tar.gz", &archive[..archive.len() / 2]).unwrap();
+    ///
+    /// let result = Extractor::new("./truncated.tar.gz", "./output")
+    ///     .threads(2)
+    ///     .extract();
+    /// let Err(Error::CorruptArchive { path, .. }) = result else {
+    ///     panic!("the truncated archive was extracted");
+    /// };
+    /// assert!(path.ends_with("truncated.tar.gz"));
+    /// ```
+    ///
+    /// A member is never inflated past the 64 KiB a BGZF member may hold, even before the
+    /// [`Extractor::limits`] see its content:
+    ///
+    /// ```
+    /// use comprexor::{Error, Extractor};
+    ///
+    /// # use std::io::Read;
+    /// # // A BGZF member: a gzip member with its size less one in the `BC` extra subfield
+    /// # fn bgzf_member(data: &[u8]) -> Vec<u8> {
+    /// #     let mut deflated = Vec::new();
+    /// #     flate2::read::DeflateEncoder::new(data, flate2::Compression::default())
+    /// #         .read_to_end(&mut deflated)
+    /// #         .unwrap();
+    /// #     let mut crc = flate2::Crc::new();
+    /// #     crc.update(data);
+    /// #     let size = u16::try_from(18 + deflated.len() + 8 - 1).unwrap();
+    /// #     let mut member = vec![0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 255, 6, 0, b'B', b'C', 2, 0];
+    /// #     member.extend_from_slice(&size.to_le_bytes());
+    /// #     member.extend_from_slice(&deflated);
+    /// #     member.extend_from_slice(&crc.sum().to_le_bytes());
+    /// #     member.extend_from_slice(&crc.amount().to_le_bytes());
+    /// #     member
+    /// # }
+    /// // A single member with 1 MiB of zeros
+    /// let mut builder = tar::Builder::new(Vec::new());
+    /// let mut header = tar::Header::new_gnu();
+    /// header.set_size(1 << 20);
+    /// header.set_mode(0o644);
+    /// builder.append_data(&mut header, "zeros", std::io::repeat(0).take(1 << 20)).unwrap();
+    /// let archive = bgzf_member(&builder.into_inner().unwrap());
+    ///
+    /// let result = Extractor::in_memory()
+    ///     .threads(2)
+    ///     .extract_to_map_from_reader(archive.as_slice());
+    /// assert!(matches!(result, Err(Error::CorruptArchive { .. })));
+    /// ```
+    ///
+    /// 0 threads is rejected before the archive is read, whatever its format:
+    ///
+    /// ```
+    /// use comprexor::{CompressionLevel, Compressor, Error, Extractor, Format};
+    ///
+    /// let archive = Compressor::in_memory()
+    ///     .format(Format::Zip)
+    ///     .add_bytes("file.txt", "content", 0o644)
+    ///     .build()
+    ///     .unwrap()
+    ///     .compress_to_vec(CompressionLevel::Default)
+    ///     .unwrap();
+    ///
+    /// let extractor = Extractor::in_memory().threads(0);
+    /// let result = extractor.list_from_reader(archive.as_slice());
+    /// assert!(matches!(result, Err(Error::InvalidOption { option: "threads", .. })));
+    /// let result = extractor.extract_to_map_from_reader(archive.as_slice());
+    /// assert!(matches!(result, Err(Error::InvalidOption { option: "threads", .. })));
+    /// ```
+    #[must_use]
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
 
+    /// Decompress the input file to the output file
+    ///
+    /// The format of the input is detected from its content unless it was set with [`Extractor::with_format`]
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::Extractor;
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::write("./file.txt", "content").unwrap();
+    /// # comprexor::Compressor::new("./file.txt", "./compacted-archive.tar.gz").compress(comprexor::CompressionLevel::Default).unwrap();
+    /// let extractor = Extractor::new("./compacted-archive.tar.gz", "./output-folder-or-file");
+    /// extractor.extract().unwrap();
+    /// ```
+    ///
+    /// Failures can be matched on:
+    ///
+    /// ```
+    /// use comprexor::{Error, Extractor};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// std::fs::write("./not-an-archive.tar.gz", "plain text").unwrap();
+    ///
+    /// let extractor = Extractor::new("./not-an-archive.tar.gz", "./output");
+    /// assert!(matches!(extractor.extract(), Err(Error::UnknownFormat { .. })));
+    ///
+    /// // A gzip header followed by garbage
+    /// std::fs::write("./corrupt.tar.gz", [0x1f, 0x8b, 0xff, 0xff]).unwrap();
+    ///
+    /// let extractor = Extractor::new("./corrupt.tar.gz", "./output");
+    /// assert!(matches!(extractor.extract(), Err(Error::CorruptArchive { .. })));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the input file is not a valid archive of the extractor format or something goes wrong while decompressing
+    pub fn extract(&self) -> Result<ArchiveInfo, Error> {
+        let archive_data = self.extract_internal()?;
         Ok(archive_data)
     }
 
-    fn compress_with_tar(&self, level: &CompressionLevel) -> Result<ArchiveInfo, std::io::Error> {
-        let mut tmpfile = NamedTempFile::new()?;
-        let mut tar = tar::Builder::new(tmpfile.reopen()?);
-
-        if std::fs::metadata(self.input)?.is_dir() {
-            let folder_name = std::path::Path::new(self.input)
-                .file_name()
-                .ok_or(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "Could not get file name from input",
-                ))?
-                .to_str()
-                .ok_or(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "Could not convert file name to str",
-                ))?;
-            tar.append_dir_all(folder_name, self.input)?;
-        } else if std::fs::metadata(self.input)?.is_file()
-            || std::fs::metadata(self.input)?.is_symlink()
-        {
-            let mut file = std::fs::File::open(self.input)?;
-            tar.append_file(self.input, &mut file)?;
-        } else {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Input is neither a file, symlink or a directory",
-            ));
-        }
-
-        tar.finish()?;
-        tmpfile.seek(SeekFrom::Start(0))?;
-
-        let archive_data = self.compress_internal(&mut tmpfile.reopen()?, level)?;
-
-        // By closing the `TempPath` explicitly, we can check that it has
-        // been deleted successfully. If we don't close it explicitly, the
-        // file will still be deleted when `file` goes out of scope, but we
-        // won't know whether deleting the file succeeded.
-        tmpfile.close()?;
-
-        Ok(archive_data)
+    /// Decompress an archive read from `reader` into the output location
+    ///
+    /// The tar stream is unpacked while it is being decompressed, so `reader` can be a socket,
+    /// an HTTP body or an in-memory buffer. The input path given to [`Extractor::new`] is not used.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CompressionLevel, Compressor, Extractor};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// std::fs::write("./file.txt", "content").unwrap();
+    ///
+    /// let mut buffer = Vec::new();
+    /// let compressor = Compressor::new("./file.txt", "");
+    /// compressor.compress_to_writer(&mut buffer, CompressionLevel::Default).unwrap();
+    ///
+    /// let extractor = Extractor::new("", "./output");
+    /// extractor.extract_from_reader(buffer.as_slice()).unwrap();
+    ///
+    /// assert_eq!(std::fs::read_to_string("./output/file.txt").unwrap(), "content");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the data is not a valid archive of the extractor format or something goes wrong while decompressing
+    pub fn extract_from_reader<R: Read>(&self, reader: R) -> Result<ArchiveInfo, Error> {
+        self.extract_stream(reader, &self.output, None)
     }
 
-    fn compress_internal(
+    /// Decompress the file at `path` inside the input archive into `writer`, returns its size
+    ///
+    /// Reading the archive stops once the file was found, the entries before it are
+    /// decompressed but not written anywhere. Only regular files are found, and the output
+    /// location and the selection of [`Extractor::include`] are not used.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{Compressor, Extractor};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// std::fs::create_dir("./artifact").unwrap();
+    /// std::fs::write("./artifact/manifest.json", "{}").unwrap();
+    /// Compressor::new("./artifact", "./artifact.tar.gz").run().unwrap();
+    ///
+    /// let mut manifest = Vec::new();
+    /// let extractor = Extractor::new("./artifact.tar.gz", "");
+    /// extractor.extract_entry("artifact/manifest.json", &mut manifest).unwrap();
+    /// assert_eq!(manifest, b"{}");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return [`Error::EntryNotFound`] if the archive has no file at `path`,
+    /// or an error if the input is not a valid archive or something goes wrong while decompressing
+    pub fn extract_entry<P: AsRef<Path>, W: Write>(
         &self,
-        input_file: &mut File,
-        level: &CompressionLevel,
-    ) -> Result<ArchiveInfo, std::io::Error> {
-        let input_size = input_file.metadata()?.len();
-        let output_file = std::fs::File::create(self.output)?;
-
-        let mut encoder = GzEncoder::new(
-            output_file,
-            level
-                .try_into()
-                .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?,
-        );
-        copy(input_file, &mut encoder)?;
-        encoder.finish()?;
-        let output_size = std::fs::metadata(self.output)?.len();
+        path: P,
+        writer: W,
+    ) -> Result<u64, Error> {
+        self.check_options()?;
+        let input = &self.input;
+        let input_file = std::fs::File::open(input).context(input, Phase::Decoding)?;
+        let input_size = input_file.metadata().ok().map(|metadata| metadata.len());
+
+        let tracker = Tracker::new(self.progress.as_ref(), self.cancel.as_ref(), input_size)
+            .with_limits(self.limits);
+        let wanted = select::components(path.as_ref());
+        let (format, reader) = self.sniff(BufReader::new(input_file), input, &tracker)?;
+        let size = match format.codec() {
+            Some(codec) => {
+                let mut archive = Archive::new(self.decoder(codec, reader)?);
+                select::read_tar_entry(&mut archive, input, &wanted, writer, &tracker)?
+            }
+            None => {
+                let zip = ZipReader::new(BufReader::new(reader));
+                select::read_zip_entry(zip, input, &wanted, writer, &tracker)?
+            }
+        };
+        size.ok_or_else(|| Error::EntryNotFound {
+            path: input.clone(),
+            entry: path.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Decompress the file at `path` inside the input archive into memory, see [`Extractor::extract_entry`]
+    ///
+    /// # Errors
+    ///
+    /// This function will return [`Error::EntryNotFound`] if the archive has no file at `path`,
+    /// or an error if the input is not a valid archive or something goes wrong while decompressing
+    pub fn extract_entry_to_vec<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>, Error> {
+        let mut content = Vec::new();
+        self.extract_entry(path, &mut content)?;
+        Ok(content)
+    }
+
+    /// Read the entries of the input archive without writing anything to disk
+    ///
+    /// The whole archive is decompressed to check it, so the sizes of the returned
+    /// [`Listing::info`] are the ones an extraction would report. The progress callback,
+    /// the cancellation token and the limits are used the same way as by an extraction.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{Compressor, EntryKind, Extractor};
+    /// use std::path::Path;
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// std::fs::create_dir("./folder").unwrap();
+    /// std::fs::write("./folder/file.txt", "content").unwrap();
+    /// Compressor::new("./folder", "./archive.tar.gz").run().unwrap();
+    ///
+    /// let listing = Extractor::new("./archive.tar.gz", "").list().unwrap();
+    /// let file = &listing.entries()[1];
+    /// assert_eq!(file.path(), Path::new("folder/file.txt"));
+    /// assert_eq!(file.kind(), EntryKind::File);
+    /// assert_eq!(file.size(), 7);
+    ///
+    /// let info = Extractor::new("./archive.tar.gz", "./output").extract().unwrap();
+    /// assert_eq!(listing.info().output_size(), info.output_size());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the input file is not a valid archive of the extractor format or something goes wrong while decompressing
+    pub fn list(&self) -> Result<Listing, Error> {
+        let input = &self.input;
+        let input_file = std::fs::File::open(input).context(input, Phase::Decoding)?;
+        let input_size = input_file.metadata().ok().map(|metadata| metadata.len());
+
+        self.list_stream(BufReader::new(input_file), input, input_size)
+    }
+
+    /// Read the entries of an archive read from `reader`, see [`Extractor::list`]
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the data is not a valid archive of the extractor format or something goes wrong while decompressing
+    pub fn list_from_reader<R: Read>(&self, reader: R) -> Result<Listing, Error> {
+        self.list_stream(reader, Path::new(""), None)
+    }
+
+    fn list_stream<R: Read>(
+        &self,
+        reader: R,
+        source: &Path,
+        input_size: Option<u64>,
+    ) -> Result<Listing, Error> {
+        self.check_options()?;
+        let tracker = Tracker::new(self.progress.as_ref(), self.cancel.as_ref(), input_size)
+            .with_limits(self.limits);
+        let (format, reader) = self.sniff(reader, source, &tracker)?;
+
+        let Some(codec) = format.codec() else {
+            let zip = ZipReader::new(BufReader::new(reader));
+            let (entries, mut reader) = list::list_zip(zip, source, &tracker)?;
+            copy(&mut reader, &mut std::io::sink()).context(source, Phase::Decoding)?;
+            let input_size = reader.get_ref().get_ref().1.count();
+            let output_size = entries.iter().map(ArchiveEntry::size).sum();
+            return Ok(Listing::new(
+                entries,
+                ArchiveInfo {
+                    input_size,
+                    output_size,
+                    ratio: output_size as f64 / input_size as f64,
+                    format,
+                    rejected: Vec::new(),
+                    conflicts: Vec::new(),
+                },
+            ));
+        };
+
+        let decoder = self.decoder(codec, reader)?;
+        let mut archive = Archive::new(CountingReader::new(Tracked::new(
+            decoder,
+            &tracker,
+            Count::Written,
+        )));
+        let entries = list::list_tar(&mut archive, source, &tracker)?;
+
+        let mut decoded = archive.into_inner();
+        copy(&mut decoded, &mut std::io::sink()).context(source, Phase::Decoding)?;
+
+        let input_size = decoded.get_ref().get_ref().get_ref().get_ref().1.count();
+        let output_size = decoded.count();
+
+        Ok(Listing::new(
+            entries,
+            ArchiveInfo {
+                input_size,
+                output_size,
+                ratio: output_size as f64 / input_size as f64,
+                format,
+                rejected: Vec::new(),
+                conflicts: Vec::new(),
+            },
+        ))
+    }
+
+    fn extract_internal(&self) -> Result<ArchiveInfo, Error> {
+        let input = &self.input;
+        let input_file = std::fs::File::open(input).context(input, Phase::Decoding)?;
+        let input_size = input_file.metadata().ok().map(|metadata| metadata.len());
+
+        self.extract_stream(BufReader::new(input_file), input, input_size)
+    }
+
+    /// Decompress the files of the input archive into memory, by their path inside the archive
+    ///
+    /// Nothing is written to the output directory. Only regular files are returned, their
+    /// paths have no `.` components, and a file stored twice keeps its last content. An entry
+    /// with an absolute path or a `..` component fails with [`Error::PathTraversal`].
+    /// [`Extractor::include`], [`Extractor::include_path`] and [`Extractor::strip_components`]
+    /// apply, and so do the limits, which are the way to bound the memory used.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CompressionLevel, Compressor, Extractor};
+    /// use std::path::Path;
+    ///
+    /// let archive = Compressor::in_memory()
+    ///     .add_bytes("folder/file.txt", "content", 0o644)
+    ///     .build()
+    ///     .unwrap()
+    ///     .compress_to_vec(CompressionLevel::Default)
+    ///     .unwrap();
+    ///
+    /// let files = Extractor::in_memory()
+    ///     .extract_to_map_from_reader(archive.as_slice())
+    ///     .unwrap();
+    /// assert_eq!(files[Path::new("folder/file.txt")], b"content");
+    /// ```
+    ///
+    /// A `..` component is an error rather than being dropped, which would make `a/../b.txt`
+    /// replace `b.txt`:
+    ///
+    /// ```
+    /// use comprexor::{Error, Extractor};
+    ///
+    /// let mut builder = tar::Builder::new(Vec::new());
+    /// for name in ["b.txt", "a/../b.txt"] {
+    ///     let mut header = tar::Header::new_old();
+    ///     header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+    ///     header.set_mode(0o644);
+    ///     header.set_size(0);
+    ///     header.set_cksum();
+    ///     builder.append(&header, std::io::empty()).unwrap();
+    /// }
+    /// let archive = builder.into_inner().unwrap();
+    ///
+    /// let result = Extractor::in_memory().extract_to_map_from_reader(archive.as_slice());
+    /// assert!(matches!(result, Err(Error::PathTraversal { .. })));
+    /// ```
+    ///
+    /// A zip archive may store a symlink and a regular file under the same name, only the
+    /// last one counts:
+    ///
+    /// ```
+    /// use comprexor::{CompressionLevel, Compressor, Extractor, Format};
+    /// use std::path::Path;
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # #[cfg(unix)]
+    /// # {
+    /// std::fs::write("./target.txt", "target").unwrap();
+    /// std::os::unix::fs::symlink("target.txt", "./name.txt").unwrap();
+    ///
+    /// let archive = Compressor::in_memory()
+    ///     .format(Format::Zip)
+    ///     .follow_symlinks(false)
+    ///     .add_input("./name.txt")
+    ///     .add_bytes("name.txt", "content", 0o644)
+    ///     .build()
+    ///     .unwrap()
+    ///     .compress_to_vec(CompressionLevel::Default)
+    ///     .unwrap();
+    ///
+    /// let files = Extractor::in_memory()
+    ///     .extract_to_map_from_reader(archive.as_slice())
+    ///     .unwrap();
+    /// assert_eq!(files[Path::new("name.txt")], b"content");
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the input file is not a valid archive of the extractor format or something goes wrong while decompressing
+    pub fn extract_to_map(&self) -> Result<BTreeMap<PathBuf, Vec<u8>>, Error> {
+        let input = &self.input;
+        let input_file = std::fs::File::open(input).context(input, Phase::Decoding)?;
+        let input_size = input_file.metadata().ok().map(|metadata| metadata.len());
+
+        self.extract_map_stream(BufReader::new(input_file), input, input_size)
+    }
+
+    /// Decompress the files of an archive read from `reader` into memory, see [`Extractor::extract_to_map`]
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the data is not a valid archive of the extractor format or something goes wrong while decompressing
+    pub fn extract_to_map_from_reader<R: Read>(
+        &self,
+        reader: R,
+    ) -> Result<BTreeMap<PathBuf, Vec<u8>>, Error> {
+        self.extract_map_stream(reader, Path::new(""), None)
+    }
+
+    fn extract_map_stream<R: Read>(
+        &self,
+        reader: R,
+        source: &Path,
+        input_size: Option<u64>,
+    ) -> Result<BTreeMap<PathBuf, Vec<u8>>, Error> {
+        self.check_options()?;
+        let tracker = Tracker::new(self.progress.as_ref(), self.cancel.as_ref(), input_size)
+            .with_limits(self.limits);
+        let selection = Selection::new(&self.include_paths, &self.include)?;
+        let selection = selection.as_ref();
+        let strip = self.strip_components;
+        let (format, reader) = self.sniff(reader, source, &tracker)?;
+
+        let Some(codec) = format.codec() else {
+            let zip = ZipReader::new(BufReader::new(reader));
+            let (files, mut reader) =
+                memory::read_zip_files(zip, source, selection, strip, &tracker)?;
+            copy(&mut reader, &mut std::io::sink()).context(source, Phase::Decoding)?;
+            return Ok(files);
+        };
+
+        let mut archive = Archive::new(self.decoder(codec, reader)?);
+        let files = memory::read_tar_files(&mut archive, source, selection, strip, &tracker)?;
+        // Read the rest of the stream so the codec trailer is verified
+        copy(&mut archive.into_inner(), &mut std::io::sink()).context(source, Phase::Decoding)?;
+        Ok(files)
+    }
+
+    /// Decompress and unpack `reader` in a single pass, `source` is only used in errors
+    ///
+    /// `input_size` is the size of the archive, if known, it is only used to report the progress
+    fn extract_stream<R: Read>(
+        &self,
+        reader: R,
+        source: &Path,
+        input_size: Option<u64>,
+    ) -> Result<ArchiveInfo, Error> {
+        self.check_options()?;
+        let tracker = Tracker::new(self.progress.as_ref(), self.cancel.as_ref(), input_size)
+            .with_limits(self.limits);
+        self.extract_tracked(reader, source, &tracker)
+            .inspect_err(|err| {
+                // Only a stopped extraction is undone, other errors leave what was extracted for inspection
+                if matches!(err, Error::Cancelled { .. } | Error::LimitExceeded { .. }) {
+                    tracker.remove_created();
+                }
+            })
+    }
+
+    fn extract_tracked<R: Read>(
+        &self,
+        reader: R,
+        source: &Path,
+        tracker: &Tracker<'_>,
+    ) -> Result<ArchiveInfo, Error> {
+        let selection = Selection::new(&self.include_paths, &self.include)?;
+        let selection = selection.as_ref();
+        let (format, reader) = self.sniff(reader, source, tracker)?;
+        let Some(codec) = format.codec() else {
+            return self.extract_zip(BufReader::new(reader), source, selection, tracker);
+        };
+
+        let decoder = self.decoder(codec, reader)?;
+        let mut archive = Archive::new(CountingReader::new(Tracked::new(
+            decoder,
+            tracker,
+            Count::Written,
+        )));
+        let (rejected, conflicts) = self.unpack(&mut archive, source, selection, tracker)?;
+
+        // tar stops reading at the end-of-archive marker, read the rest of the
+        // stream so the codec trailer is verified and the sizes are complete
+        let mut decoded = archive.into_inner();
+        copy(&mut decoded, &mut std::io::sink()).context(source, Phase::Decoding)?;
+
+        let input_size = decoded.get_ref().get_ref().get_ref().get_ref().1.count();
+        let output_size = decoded.count();
+
+        Ok(ArchiveInfo {
+            input_size,
+            output_size,
+            ratio: output_size as f64 / input_size as f64,
+            format,
+            rejected,
+            conflicts,
+        })
+    }
+
+    /// Detect the format of `reader`, or use the one set with [`Extractor::with_format`]
+    ///
+    /// Returns the format with a reader that still starts at the first byte of the archive
+    fn sniff<'t, R: Read>(
+        &self,
+        reader: R,
+        source: &Path,
+        tracker: &'t Tracker<'t>,
+    ) -> Result<(Format, Sniffed<Tracked<'t, R>>), Error> {
+        let mut reader = CountingReader::new(Tracked::new(reader, tracker, Count::Read));
+        let header = read_header(&mut reader).context(source, Phase::Decoding)?;
+        let format = match self.format.or_else(|| Format::detect(&header)) {
+            Some(format) => format,
+            None => {
+                return Err(match format::detect_unsupported(&header) {
+                    Some(format) => Error::UnsupportedFormat {
+                        path: source.to_path_buf(),
+                        format,
+                    },
+                    None => Error::UnknownFormat {
+                        path: source.to_path_buf(),
+                    },
+                })
+            }
+        };
+
+        Ok((format, Cursor::new(header).chain(reader)))
+    }
+
+    /// Decompress `reader` with `codec`, on several threads when the archive is made of independent blocks
+    /// Check the options that apply to every format, before anything is read
+    fn check_options(&self) -> Result<(), Error> {
+        if self.threads == 0 {
+            return Err(Error::InvalidOption {
+                option: "threads",
+                reason: "at least one thread is needed".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn decoder<R: Read>(
+        &self,
+        codec: Codec,
+        reader: Sniffed<R>,
+    ) -> Result<Decoder<Sniffed<R>>, Error> {
+        let header = reader.get_ref().0.get_ref();
+        if self.threads > 1 && codec == Codec::Gzip && parallel::is_bgzf(header) {
+            return Ok(Decoder::parallel_gzip(reader, self.threads));
+        }
+        Ok(Decoder::new(codec, reader))
+    }
+
+    /// Unpack all entries of `archive` into the output directory, returns the rejected entries and the conflicts
+    ///
+    /// Directories are created last so that their permissions do not prevent
+    /// their children from being written, the same way `tar::Archive::unpack` does
+    fn unpack<R: Read>(
+        &self,
+        archive: &mut Archive<R>,
+        source: &Path,
+        selection: Option<&Selection>,
+        tracker: &Tracker<'_>,
+    ) -> Result<(Vec<RejectedEntry>, Vec<Conflict>), Error> {
+        let output = self.prepare_output(tracker)?;
+
+        let mut directories = Vec::new();
+        let mut rejected = Vec::new();
+        let mut conflicts = Vec::new();
+        for entry in archive.entries().context(source, Phase::Unpacking)? {
+            let entry = entry.context(source, Phase::Unpacking)?;
+            let name = entry.path().context(source, Phase::Unpacking)?.into_owned();
+            tracker
+                .read_entry(&name)
+                .context(source, Phase::Unpacking)?;
+            let is_dir = entry.header().entry_type() == EntryType::Directory;
+            if selection.is_some_and(|selection| !selection.matches(&name, is_dir)) {
+                continue;
+            }
+            let Some(relative) = strip_path(&name, self.strip_components) else {
+                continue;
+            };
+            if self.hardened {
+                let reason =
+                    harden::check_tar_entry(&entry, &relative, &output, self.strip_components)
+                        .context(source, Phase::Unpacking)?;
+                if let Some(reason) = reason {
+                    rejected.push(RejectedEntry::new(name, reason));
+                    continue;
+                }
+            }
+            if is_dir {
+                directories.push((entry, relative));
+            } else {
+                self.unpack_entry(entry, source, &relative, &output, &mut conflicts, tracker)?;
+            }
+        }
+
+        directories.sort_by(|a, b| b.1.cmp(&a.1));
+        for (entry, relative) in directories {
+            self.unpack_entry(entry, source, &relative, &output, &mut conflicts, tracker)?;
+        }
+
+        Ok((rejected, conflicts))
+    }
+
+    /// Create the output directory and return its canonical path
+    fn prepare_output(&self, tracker: &Tracker<'_>) -> Result<PathBuf, Error> {
+        let output = &self.output;
+        if output.symlink_metadata().is_err() {
+            tracker.will_create(output, Path::new(""));
+            std::fs::create_dir_all(output).context(output, Phase::Unpacking)?;
+        }
+        Ok(output
+            .canonicalize()
+            .unwrap_or_else(|_| output.to_path_buf()))
+    }
+
+    /// Unpack a zip archive read from `reader` into the output directory
+    ///
+    /// The unix modes of the entries are stored in the central directory at the end
+    /// of the archive, they are applied once all the entries were written
+    fn extract_zip<R: Read>(
+        &self,
+        reader: BufReader<Sniffed<R>>,
+        source: &Path,
+        selection: Option<&Selection>,
+        tracker: &Tracker<'_>,
+    ) -> Result<ArchiveInfo, Error> {
+        let output = self.prepare_output(tracker)?;
+        let mut zip = ZipReader::new(reader);
+        let mut output_size = 0;
+        let mut rejected = Vec::new();
+        let mut conflicts = Vec::new();
+        // The local header each path was last written from, for the modes of the central directory
+        let mut written = HashMap::new();
+
+        while let Some(entry) = zip.next_entry().context(source, Phase::Unpacking)? {
+            tracker
+                .read_entry(Path::new(&os_str(&entry.name)))
+                .context(source, Phase::Unpacking)?;
+            if selection.is_some_and(|selection| {
+                !selection.matches(Path::new(&os_str(&entry.name)), entry.is_dir())
+            }) {
+                zip.read_data(&entry, &mut std::io::sink())
+                    .context(source, Phase::Unpacking)?;
+                continue;
+            }
+            if let Some(reason) = self
+                .hardened
+                .then(|| harden::check_zip_name(&entry.name))
+                .flatten()
+            {
+                zip.read_data(&entry, &mut std::io::sink())
+                    .context(source, Phase::Unpacking)?;
+                rejected.push(RejectedEntry::new(
+                    PathBuf::from(&*os_str(&entry.name)),
+                    reason,
+                ));
+                continue;
+            }
+            let path = match zip_entry_path(&entry.name, &output, self.strip_components)? {
+                Some(path) if entry.is_dir() => Some(path),
+                Some(path) => {
+                    overwrite::resolve(self.overwrite, &path, entry.mtime, &mut conflicts)?
+                }
+                None => None,
+            };
+            let Some(path) = path else {
+                zip.read_data(&entry, &mut std::io::sink())
+                    .context(source, Phase::Unpacking)?;
+                continue;
+            };
+            tracker
+                .start_entry(Path::new(&os_str(&entry.name)))
+                .context(source, Phase::Unpacking)?;
+            tracker.will_create(&path, &output);
+
+            if entry.is_dir() {
+                std::fs::create_dir_all(&path).context(&path, Phase::Unpacking)?;
+                zip.read_data(&entry, &mut std::io::sink())
+                    .context(source, Phase::Unpacking)?;
+                tracker.finish_entry();
+                written.insert(path, entry.offset);
+                continue;
+            }
+
+            // The parent may be a symlink that was extracted earlier
+            create_parents(&path, &output)?;
+            // Never write through an existing symlink
+            if path.symlink_metadata().is_ok_and(|meta| meta.is_symlink()) {
+                std::fs::remove_file(&path).context(&path, Phase::Unpacking)?;
+            }
+
+            let file = std::fs::File::create(&path).context(&path, Phase::Unpacking)?;
+            let mut file = BufWriter::new(Tracked::new(file, tracker, Count::Written));
+            output_size += zip
+                .read_data(&entry, &mut file)
+                .map_err(|err| unpack_error(source, &path, err))?;
+            let file = file
+                .into_inner()
+                .map_err(|err| Error::io(&path, Phase::Unpacking, err.into_error()))?
+                .into_inner();
+            file.set_modified(UNIX_EPOCH + std::time::Duration::from_secs(entry.mtime))
+                .context(&path, Phase::Unpacking)?;
+            tracker.finish_entry();
+            written.insert(path, entry.offset);
+        }
+
+        let (central, mut reader) = zip.central_directory().context(source, Phase::Unpacking)?;
+        let written = written
+            .into_iter()
+            .map(|(path, offset)| (offset, path))
+            .collect();
+        self.apply_zip_modes(&central, &output, &written, &mut rejected)?;
+
+        copy(&mut reader, &mut std::io::sink()).context(source, Phase::Decoding)?;
+        let input_size = reader.get_ref().get_ref().1.count();
+
+        Ok(ArchiveInfo {
+            input_size,
+            output_size,
+            ratio: output_size as f64 / input_size as f64,
+            format: Format::Zip,
+            rejected,
+            conflicts,
+        })
+    }
+
+    /// Restore the unix modes from the central directory of a zip archive
+    ///
+    /// Only the entries in `written`, by the offset of their local header and with the path
+    /// they were written at, are updated.
+    /// Entries stored as symlinks were written as regular files holding the link target,
+    /// they are turned into actual symlinks here. When hardened, the symlinks pointing
+    /// outside of `output` are removed and added to `rejected`.
+    fn apply_zip_modes(
+        &self,
+        central: &[zip::CentralEntry],
+        output: &Path,
+        written: &HashMap<u64, PathBuf>,
+        rejected: &mut Vec<RejectedEntry>,
+    ) -> Result<(), Error> {
+        let mut directories = Vec::new();
+        for entry in central {
+            let (Some(mode), Some(path)) = (entry.mode, written.get(&entry.offset)) else {
+                continue;
+            };
+            match mode & zip::MODE_TYPE_MASK {
+                zip::MODE_DIRECTORY => directories.push((path, mode)),
+                zip::MODE_SYMLINK => {
+                    let target = std::fs::read(path).context(path, Phase::Unpacking)?;
+                    let link_dir = path
+                        .parent()
+                        .and_then(|parent| parent.strip_prefix(output).ok())
+                        .unwrap_or(Path::new(""));
+                    if self.hardened
+                        && !harden::link_stays_inside(output, link_dir, Path::new(&os_str(&target)))
+                    {
+                        std::fs::remove_file(path).context(path, Phase::Unpacking)?;
+                        rejected.push(RejectedEntry::new(
+                            PathBuf::from(&*os_str(&entry.name)),
+                            RejectReason::LinkOutsideDestination,
+                        ));
+                        continue;
+                    }
+                    make_symlink(path, &target)?;
+                }
+                _ => set_mode(path, mode)?,
+            }
+        }
+
+        // Children first, so a read only directory does not prevent updating its content
+        directories.sort_by(|a, b| b.0.cmp(a.0));
+        for (path, mode) in directories {
+            set_mode(path, mode)?;
+        }
+        Ok(())
+    }
+
+    /// Unpack `entry` of the archive at `source` at `relative`, its path inside of `output` once stripped
+    ///
+    /// Entries other than directories go through the overwrite policy when their path exists
+    fn unpack_entry<R: Read>(
+        &self,
+        mut entry: tar::Entry<'_, R>,
+        source: &Path,
+        relative: &Path,
+        output: &Path,
+        conflicts: &mut Vec<Conflict>,
+        tracker: &Tracker<'_>,
+    ) -> Result<(), Error> {
+        let name = entry.path().context(output, Phase::Unpacking)?.into_owned();
+        let Some(natural) = output_path(relative, output) else {
+            return Err(Error::PathTraversal {
+                path: output.join(relative),
+            });
+        };
+        let path = if entry.header().entry_type() == EntryType::Directory {
+            natural.clone()
+        } else {
+            let mtime = entry.header().mtime().unwrap_or(0);
+            match overwrite::resolve(self.overwrite, &natural, mtime, conflicts)? {
+                Some(path) => path,
+                None => return Ok(()),
+            }
+        };
+
+        tracker
+            .start_entry(&name)
+            .and_then(|()| tracker.check_entry_size(entry.size()))
+            .context(&path, Phase::Unpacking)?;
+        tracker.will_create(&path, output);
+        // tar can only unpack an entry at its own path
+        let unpacked = if self.strip_components == 0 && path == natural {
+            entry
+                .unpack_in(output)
+                .map_err(|err| unpack_error(source, &path, err))?
+        } else {
+            unpack_at(&mut entry, source, &path, output, self.strip_components)?
+        };
+        if unpacked {
+            tracker.finish_entry();
+            Ok(())
+        } else {
+            Err(Error::PathTraversal { path })
+        }
+    }
+}
+
+impl Compressor {
+    #[must_use]
+    /// Creates a new compressor with the given input and output
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CompressionLevel, Compressor};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::create_dir("./folder-or-file-to-compress").unwrap();
+    /// let compressor = Compressor::new("./folder-or-file-to-compress", "./compacted-archive.tar.gz");
+    /// compressor.compress(CompressionLevel::Maximum).unwrap();
+    /// ```
+    ///
+    /// Paths do not need to be UTF-8, and the compressor owns them so it can be moved to another thread:
+    ///
+    /// ```
+    /// use comprexor::{CompressionLevel, Compressor};
+    /// use std::path::PathBuf;
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::create_dir("./folder").unwrap();
+    /// let input = PathBuf::from("./folder");
+    /// let compressor = Compressor::new(&input, input.with_extension("tar.gz"));
+    /// std::thread::spawn(move || compressor.compress(CompressionLevel::Default))
+    ///     .join()
+    ///     .unwrap()
+    ///     .unwrap();
+    /// ```
+    pub fn new<I: AsRef<Path>, O: AsRef<Path>>(input: I, output: O) -> Compressor {
+        Self {
+            inputs: vec![Input::new(input.as_ref())],
+            output: output.as_ref().to_path_buf(),
+            format: Format::default(),
+            level: CompressionLevel::default(),
+            follow_symlinks: true,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            respect_ignore_files: false,
+            contents_at_root: false,
+            reproducible: false,
+            files: Vec::new(),
+            threads: 1,
+            progress: None,
+            cancel: None,
+        }
+    }
+
+    /// Create a builder to configure a compressor of `input` into `output`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CompressionLevel, Compressor, Extractor, Format};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::create_dir("./folder-or-file-to-compress").unwrap();
+    /// # std::fs::write("./folder-or-file-to-compress/file.txt", "content").unwrap();
+    /// let format = Format::Zip;
+    /// let output = format!("./compacted-archive.{}", format.extension());
+    /// let compressor = Compressor::builder("./folder-or-file-to-compress", &output)
+    ///     .format(format)
+    ///     .root_name("renamed")
+    ///     .build()
+    ///     .unwrap();
+    /// compressor.run().unwrap();
+    ///
+    /// let archive_data = Extractor::new(&output, "./output").extract().unwrap();
+    /// assert_eq!(archive_data.format(), Format::Zip);
+    /// assert_eq!(
+    ///     std::fs::read_to_string("./output/renamed/file.txt").unwrap(),
+    ///     "content"
+    /// );
+    /// ```
+    #[must_use]
+    pub fn builder<I: AsRef<Path>, O: AsRef<Path>>(input: I, output: O) -> CompressorBuilder {
+        CompressorBuilder::new(input, output)
+    }
+
+    /// Create a builder for an archive without inputs from the file system, see [`CompressorBuilder::in_memory`]
+    #[must_use]
+    pub fn in_memory() -> CompressorBuilder {
+        CompressorBuilder::in_memory()
+    }
+
+    /// Get the compression level used by [`Compressor::run`]
+    #[must_use]
+    pub fn level(&self) -> &CompressionLevel {
+        &self.level
+    }
+
+    /// Compress the inputs to the output location with the options of the compressor
+    ///
+    /// This is the same as calling [`Compressor::compress`] with the level set on the [`CompressorBuilder`]
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the input can not be read or something goes wrong while compressing
+    pub fn run(&self) -> Result<ArchiveInfo, Error> {
+        self.compress(&self.level)
+    }
+
+    /// Compress the input files and folders to the output location
+    ///
+    /// You can choose the compression level with the `CompressionLevel` enum
+    ///
+    /// The compression level can be:
+    ///
+    /// - `CompressionLevel::None`
+    /// - `CompressionLevel::Fast`
+    /// - `CompressionLevel::Default`
+    /// - `CompressionLevel::Maximum`
+    /// - `CompressionLevel::Custom` (you can specify the compression level between 0 and 9)
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CompressionLevel, Compressor};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::create_dir("./folder-or-file-to-compress").unwrap();
+    /// let compressor = Compressor::new("./folder-or-file-to-compress", "./compacted-archive.tar.gz");
+    /// compressor.compress(CompressionLevel::Maximum).unwrap();
+    /// ```
+    ///
+    /// Failures can be matched on:
+    ///
+    /// ```
+    /// use comprexor::{CompressionLevel, Compressor, Error};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::create_dir("./folder-or-file-to-compress").unwrap();
+    /// let compressor = Compressor::new("./folder-or-file-to-compress", "./compacted-archive.tar.gz");
+    /// assert!(matches!(
+    ///     compressor.compress(CompressionLevel::Custom(10)),
+    ///     Err(Error::InvalidLevel(10))
+    /// ));
+    ///
+    /// let compressor = Compressor::new("./does-not-exist", "./compacted-archive.tar.gz");
+    /// assert!(matches!(
+    ///     compressor.compress(CompressionLevel::Default),
+    ///     Err(Error::InputNotFound { .. })
+    /// ));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the compression level is invalid, the input can not be read or something goes wrong while compressing
+    pub fn compress<T>(&self, level: T) -> Result<ArchiveInfo, Error>
+    where
+        T: AsRef<CompressionLevel>,
+    {
+        self.format.check_level(level.as_ref())?;
+        let output = &self.output;
+
+        // Check the inputs before creating the output, so a missing input does not leave an empty archive behind
+        for input in &self.inputs {
+            std::fs::metadata(&input.path).context(&input.path, Phase::BuildingTar)?;
+        }
+        let output_file = std::fs::File::create(output).context(output, Phase::Encoding)?;
+
+        let archive_data = self
+            .compress_archive(BufWriter::new(output_file), level.as_ref(), Some(output))
+            .inspect_err(|_| {
+                // The partial archive is useless, failing to remove it should not hide the original error
+                let _ = std::fs::remove_file(output);
+            })?;
+
+        Ok(archive_data)
+    }
+
+    /// Compress the input files and folders into `writer`
+    ///
+    /// The archive is piped directly into the encoder, so nothing is written to disk
+    /// and `writer` can be a socket, an HTTP body or an in-memory buffer. The output path given
+    /// to [`Compressor::new`] is not used.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CompressionLevel, Compressor};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::create_dir("./folder-or-file-to-compress").unwrap();
+    /// let mut buffer = Vec::new();
+    /// let compressor = Compressor::new("./folder-or-file-to-compress", "");
+    /// let archive_data = compressor
+    ///     .compress_to_writer(&mut buffer, CompressionLevel::Maximum)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(archive_data.output_size(), buffer.len() as u64);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the compression level is invalid, the input can not be read or `writer` fails
+    pub fn compress_to_writer<W, T>(&self, writer: W, level: T) -> Result<ArchiveInfo, Error>
+    where
+        W: Write,
+        T: AsRef<CompressionLevel>,
+    {
+        self.compress_archive(writer, level.as_ref(), None)
+    }
+
+    /// Compress the inputs into memory, and return the archive
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{CompressionLevel, Compressor, Extractor};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// # std::fs::create_dir("./folder").unwrap();
+    /// # std::fs::write("./folder/file.txt", "content").unwrap();
+    /// let archive = Compressor::new("./folder", "")
+    ///     .compress_to_vec(CompressionLevel::Default)
+    ///     .unwrap();
+    ///
+    /// let files = Extractor::in_memory()
+    ///     .extract_to_map_from_reader(archive.as_slice())
+    ///     .unwrap();
+    /// assert_eq!(files.len(), 1);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the compression level is invalid, the input can not be read or something goes wrong while compressing
+    pub fn compress_to_vec<T: AsRef<CompressionLevel>>(&self, level: T) -> Result<Vec<u8>, Error> {
+        let mut archive = Vec::new();
+        self.compress_to_writer(&mut archive, level)?;
+        Ok(archive)
+    }
+
+    /// Write the archive of the input into `writer`, `output` is the path
+    /// `writer` writes to, if any, it is left out of the archive
+    fn compress_archive<W: Write>(
+        &self,
+        writer: W,
+        level: &CompressionLevel,
+        output: Option<&Path>,
+    ) -> Result<ArchiveInfo, Error> {
+        let include = glob::compile("include", &self.include)?;
+        let exclude = glob::compile("exclude", &self.exclude)?;
+        let entries = Walk {
+            follow_symlinks: self.follow_symlinks,
+            skip: output,
+            include: &include,
+            exclude: &exclude,
+            respect_ignore_files: self.respect_ignore_files,
+            contents_at_root: self.contents_at_root,
+            sorted: self.reproducible,
+        }
+        .entries(&self.inputs)?;
+        let destination = output
+            .or_else(|| self.inputs.first().map(|input| input.path.as_path()))
+            .unwrap_or(Path::new(""));
+
+        match self.format.codec() {
+            Some(codec) => {
+                // Every entry has a header block, file contents are padded to full blocks
+                // and the archive ends with two empty blocks
+                let tar_size = entries
+                    .iter()
+                    .map(|entry| file_size(&entry.metadata))
+                    .chain(self.files.iter().map(|file| file.content.len() as u64))
+                    .map(|size| 512 + size.div_ceil(512) * 512)
+                    .sum::<u64>()
+                    + 1024;
+                let tracker =
+                    Tracker::new(self.progress.as_ref(), self.cancel.as_ref(), Some(tar_size));
+                let writer = Tracked::new(writer, &tracker, Count::Written);
+                self.compress_with_tar(writer, codec, level, &entries, destination, &tracker)
+            }
+            None => {
+                let input_size = entries
+                    .iter()
+                    .map(|entry| file_size(&entry.metadata))
+                    .chain(self.files.iter().map(|file| file.content.len() as u64))
+                    .sum();
+                let tracker = Tracker::new(
+                    self.progress.as_ref(),
+                    self.cancel.as_ref(),
+                    Some(input_size),
+                );
+                let writer = Tracked::new(writer, &tracker, Count::Written);
+                self.compress_with_zip(writer, level, &entries, destination, &tracker)
+            }
+        }
+    }
+
+    /// Write the tar archive of `entries` through the encoder of `codec` into `writer`,
+    /// `destination` is only used in errors
+    fn compress_with_tar<W: Write>(
+        &self,
+        writer: W,
+        codec: Codec,
+        level: &CompressionLevel,
+        entries: &[walk::InputEntry],
+        destination: &Path,
+        tracker: &Tracker<'_>,
+    ) -> Result<ArchiveInfo, Error> {
+        let encoder = Encoder::new(codec, CountingWriter::new(writer), level, self.threads)?;
+        let mut tar = tar::Builder::new(CountingWriter::new(Tracked::new(
+            encoder,
+            tracker,
+            Count::Read,
+        )));
+        tar.follow_symlinks(self.follow_symlinks);
+        let normalized_mtime = self.reproducible.then(source_date_epoch).transpose()?;
+
+        for entry in entries {
+            tracker
+                .start_entry(&entry.name)
+                .context(&entry.source, Phase::BuildingTar)?;
+            if let Some(mtime) = normalized_mtime {
+                append_reproducible(&mut tar, entry, mtime)?;
+            } else if entry.metadata.is_dir() {
+                tar.append_dir(&entry.name, &entry.source)
+                    .context(&entry.source, Phase::BuildingTar)?;
+            } else {
+                tar.append_path_with_name(&entry.source, &entry.name)
+                    .context(&entry.source, Phase::BuildingTar)?;
+            }
+            tracker.finish_entry();
+        }
+
+        let files_mtime = normalized_mtime.unwrap_or_else(now);
+        for file in &self.files {
+            let name = normalize(&file.name);
+            tracker
+                .start_entry(&name)
+                .context(destination, Phase::BuildingTar)?;
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(EntryType::Regular);
+            header.set_size(file.content.len() as u64);
+            header.set_mode(memory_file_mode(file, normalized_mtime.is_some()) & 0o7777);
+            header.set_mtime(files_mtime);
+            tar.append_data(&mut header, &name, file.content.as_slice())
+                .context(destination, Phase::BuildingTar)?;
+            tracker.finish_entry();
+        }
+
+        let tar_data = tar.into_inner().context(destination, Phase::BuildingTar)?;
+        let input_size = tar_data.count();
+        let mut output = tar_data
+            .into_inner()
+            .into_inner()
+            .finish()
+            .context(destination, Phase::Encoding)?;
+        output.flush().context(destination, Phase::Encoding)?;
+        let output_size = output.count();
 
         Ok(ArchiveInfo {
             input_size,
             output_size,
             ratio: input_size as f64 / output_size as f64,
+            format: self.format,
+            rejected: Vec::new(),
+            conflicts: Vec::new(),
         })
     }
+
+    /// Write the zip archive of `entries` into `writer`, `destination` is only used in errors
+    ///
+    /// Files are stored without compression for a level of 0 and compressed with deflate otherwise
+    fn compress_with_zip<W: Write>(
+        &self,
+        writer: W,
+        level: &CompressionLevel,
+        entries: &[walk::InputEntry],
+        destination: &Path,
+        tracker: &Tracker<'_>,
+    ) -> Result<ArchiveInfo, Error> {
+        let method = match u32::from(level) {
+            0 => zip::Method::Stored,
+            _ => zip::Method::Deflate(Compression::try_from(level)?),
+        };
+        let mut zip = ZipWriter::new(writer, method);
+        let mut input_size = 0;
+        let normalized_mtime = self.reproducible.then(source_date_epoch).transpose()?;
+
+        for entry in entries {
+            let mode = unix_mode(&entry.metadata);
+            let zip_entry = ZipEntry {
+                name: zip_entry_name(&entry.name),
+                mode: match normalized_mtime {
+                    Some(_) => normalized_mode(mode),
+                    None => mode,
+                },
+                mtime: normalized_mtime.unwrap_or_else(|| mtime(&entry.metadata)),
+            };
+            tracker
+                .start_entry(&entry.name)
+                .context(&entry.source, Phase::BuildingTar)?;
+            if entry.metadata.is_dir() {
+                zip.add_directory(zip_entry)
+                    .context(destination, Phase::Encoding)?;
+            } else if entry.metadata.is_symlink() {
+                // Like the zip tools, a symlink is stored as a file holding its target
+                let target =
+                    std::fs::read_link(&entry.source).context(&entry.source, Phase::BuildingTar)?;
+                let mut target =
+                    Tracked::new(Cursor::new(path_bytes(&target)), tracker, Count::Read);
+                input_size += zip
+                    .add_file(zip_entry, &mut target)
+                    .context(&entry.source, Phase::Encoding)?;
+            } else if entry.metadata.is_file() {
+                let file = std::fs::File::open(&entry.source)
+                    .context(&entry.source, Phase::BuildingTar)?;
+                let mut file = Tracked::new(file, tracker, Count::Read);
+                input_size += zip
+                    .add_file(zip_entry, &mut file)
+                    .context(&entry.source, Phase::Encoding)?;
+            } else {
+                return Err(Error::UnsupportedInputKind {
+                    path: entry.source.clone(),
+                });
+            }
+            tracker.finish_entry();
+        }
+
+        let files_mtime = normalized_mtime.unwrap_or_else(now);
+        for file in &self.files {
+            let name = normalize(&file.name);
+            let zip_entry = ZipEntry {
+                name: zip_entry_name(&name),
+                mode: memory_file_mode(file, normalized_mtime.is_some()),
+                mtime: files_mtime,
+            };
+            tracker
+                .start_entry(&name)
+                .context(destination, Phase::BuildingTar)?;
+            let mut content =
+                Tracked::new(Cursor::new(file.content.as_slice()), tracker, Count::Read);
+            input_size += zip
+                .add_file(zip_entry, &mut content)
+                .context(destination, Phase::Encoding)?;
+            tracker.finish_entry();
+        }
+
+        let mut output = zip.finish().context(destination, Phase::Encoding)?;
+        output.flush().context(destination, Phase::Encoding)?;
+        let output_size = output.count();
+
+        Ok(ArchiveInfo {
+            input_size,
+            output_size,
+            ratio: input_size as f64 / output_size as f64,
+            format: self.format,
+            rejected: Vec::new(),
+            conflicts: Vec::new(),
+        })
+    }
+}
+
+/// Append `entry` to `tar` with a header that only depends on its name, type, content and
+/// whether it is executable, see [`CompressorBuilder::reproducible`]
+fn append_reproducible<W: Write>(
+    tar: &mut tar::Builder<W>,
+    entry: &walk::InputEntry,
+    mtime: u64,
+) -> Result<(), Error> {
+    let mut header = tar::Header::new_gnu();
+    header.set_metadata_in_mode(&entry.metadata, HeaderMode::Deterministic);
+    header.set_mtime(mtime);
+
+    let file_type = entry.metadata.file_type();
+    if file_type.is_dir() {
+        tar.append_data(&mut header, &entry.name, std::io::empty())
+    } else if file_type.is_symlink() {
+        let target =
+            std::fs::read_link(&entry.source).context(&entry.source, Phase::BuildingTar)?;
+        tar.append_link(&mut header, &entry.name, target)
+    } else if file_type.is_file() {
+        let file = std::fs::File::open(&entry.source).context(&entry.source, Phase::BuildingTar)?;
+        tar.append_data(&mut header, &entry.name, file)
+    } else {
+        return Err(Error::UnsupportedInputKind {
+            path: entry.source.clone(),
+        });
+    }
+    .context(&entry.source, Phase::BuildingTar)
+}
+
+/// Get the permissions of a reproducible archive entry from its unix `mode`, the same as
+/// the ones of [`HeaderMode::Deterministic`] tar headers
+fn normalized_mode(mode: u32) -> u32 {
+    let permissions = match mode & zip::MODE_TYPE_MASK {
+        zip::MODE_SYMLINK => 0o777,
+        zip::MODE_DIRECTORY => 0o755,
+        _ if mode & 0o100 != 0 => 0o755,
+        _ => 0o644,
+    };
+    (mode & zip::MODE_TYPE_MASK) | permissions
+}
+
+/// Get the unix mode of a file added from memory, with the regular file type bits
+fn memory_file_mode(file: &MemoryFile, reproducible: bool) -> u32 {
+    let mode = 0o100_000 | (file.mode & 0o7777);
+    if reproducible {
+        normalized_mode(mode)
+    } else {
+        mode
+    }
+}
+
+/// Get the current time in seconds since the unix epoch, the modification time of the files added from memory
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Get the modification time of the entries of reproducible archives, `SOURCE_DATE_EPOCH`
+/// if it is set, see <https://reproducible-builds.org/specs/source-date-epoch/>
+fn source_date_epoch() -> Result<u64, Error> {
+    match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(value) if !value.is_empty() => value.parse().map_err(|_| Error::InvalidOption {
+            option: "reproducible",
+            reason: format!("`SOURCE_DATE_EPOCH` is not a number of seconds: `{value}`"),
+        }),
+        _ => Ok(tar::DETERMINISTIC_TIMESTAMP),
+    }
+}
+
+/// Get the `/` separated name of an archive entry
+fn zip_entry_name(name: &Path) -> Vec<u8> {
+    name.components()
+        .map(|component| path_bytes(Path::new(component.as_os_str())))
+        .collect::<Vec<_>>()
+        .join(&b'/')
+}
+
+/// Get where a zip entry is unpacked inside `output`, `None` for entries without a name
+/// once `strip` components are removed
+///
+/// Leading `/` are ignored, like tar does, and `..` components are rejected
+fn zip_entry_path(name: &[u8], output: &Path, strip: usize) -> Result<Option<PathBuf>, Error> {
+    let mut path = output.to_path_buf();
+    let mut empty = true;
+    let mut stripped = 0;
+    for part in name.split(|&byte| byte == b'/' || byte == b'\\') {
+        match Path::new(&os_str(part)).components().next() {
+            Some(Component::Normal(_)) if stripped < strip => stripped += 1,
+            Some(Component::Normal(part)) => {
+                path.push(part);
+                empty = false;
+            }
+            Some(Component::ParentDir) => {
+                return Err(Error::PathTraversal {
+                    path: output.join(os_str(name)),
+                })
+            }
+            _ => {}
+        }
+    }
+    Ok((!empty).then_some(path))
+}
+
+/// Remove the first `strip` names from the path of an entry, `None` when no name is left
+///
+/// The other components are kept so that absolute paths and `..` can still be detected
+fn strip_path(path: &Path, strip: usize) -> Option<PathBuf> {
+    let mut stripped = 0;
+    let path: PathBuf = path
+        .components()
+        .filter(|component| match component {
+            Component::Normal(_) if stripped < strip => {
+                stripped += 1;
+                false
+            }
+            Component::CurDir => false,
+            _ => true,
+        })
+        .collect();
+    path.components()
+        .any(|component| matches!(component, Component::Normal(_)))
+        .then_some(path)
+}
+
+/// Get where the entry at `relative` is unpacked inside `output`, `None` if it contains `..`
+///
+/// The root and prefix are ignored, like tar does
+fn output_path(relative: &Path, output: &Path) -> Option<PathBuf> {
+    let mut path = output.to_path_buf();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::ParentDir => return None,
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+        }
+    }
+    Some(path)
+}
+
+/// Unpack a tar entry of the archive at `source` at `path` inside `output` instead of at
+/// its own path, with the same checks as `tar::Entry::unpack_in`
+///
+/// Hard link targets are relative to the root of the archive, `strip` names are removed from
+/// them as well. Returns `false` if the target contains `..`.
+fn unpack_at<R: Read>(
+    entry: &mut tar::Entry<'_, R>,
+    source: &Path,
+    path: &Path,
+    output: &Path,
+    strip: usize,
+) -> Result<bool, Error> {
+    create_parents(path, output)?;
+    if entry.header().entry_type() != EntryType::Link {
+        entry
+            .unpack(path)
+            .map_err(|err| unpack_error(source, path, err))?;
+        return Ok(true);
+    }
+
+    let target = entry
+        .link_name()
+        .context(path, Phase::Unpacking)?
+        .unwrap_or_default();
+    let Some(target) = strip_path(&target, strip).and_then(|target| output_path(&target, output))
+    else {
+        return Ok(false);
+    };
+    // The target may be behind a symlink that was extracted earlier
+    if !target
+        .canonicalize()
+        .context(&target, Phase::Unpacking)?
+        .starts_with(output)
+    {
+        return Err(Error::PathTraversal {
+            path: path.to_path_buf(),
+        });
+    }
+    if path.symlink_metadata().is_ok() {
+        std::fs::remove_file(path).context(path, Phase::Unpacking)?;
+    }
+    std::fs::hard_link(&target, path).context(path, Phase::Unpacking)?;
+    Ok(true)
+}
+
+/// Wrap an error that happened while unpacking an entry of the archive at `source` to `path`
+///
+/// Errors from the decoder are about the archive, not the written file
+fn unpack_error(source: &Path, path: &Path, err: std::io::Error) -> Error {
+    match err.kind() {
+        std::io::ErrorKind::InvalidData
+        | std::io::ErrorKind::InvalidInput
+        | std::io::ErrorKind::UnexpectedEof => Error::io(source, Phase::Unpacking, err),
+        _ => Error::io(path, Phase::Unpacking, err),
+    }
+}
+
+/// Create the missing parents of `path`, which must stay inside `output` once symlinks are resolved
+///
+/// Each parent is checked before a directory is created in it, so nothing is created outside of `output`
+fn create_parents(path: &Path, output: &Path) -> Result<(), Error> {
+    let parent = path.parent().unwrap_or(output);
+    let inside = |dir: &Path| {
+        dir.canonicalize()
+            .context(dir, Phase::Unpacking)
+            .map(|dir| dir.starts_with(output))
+    };
+    let missing: Vec<&Path> = parent
+        .ancestors()
+        .take_while(|dir| dir.symlink_metadata().is_err())
+        .collect();
+    for dir in missing.into_iter().rev() {
+        if !inside(dir.parent().unwrap_or(output))? {
+            return Err(Error::PathTraversal {
+                path: path.to_path_buf(),
+            });
+        }
+        std::fs::create_dir(dir).context(dir, Phase::Unpacking)?;
+    }
+    if inside(parent)? {
+        Ok(())
+    } else {
+        Err(Error::PathTraversal {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+#[cfg(unix)]
+fn make_symlink(path: &Path, target: &[u8]) -> Result<(), Error> {
+    std::fs::remove_file(path).context(path, Phase::Unpacking)?;
+    std::os::unix::fs::symlink(os_str(target), path).context(path, Phase::Unpacking)
+}
+
+#[cfg(not(unix))]
+fn make_symlink(_path: &Path, _target: &[u8]) -> Result<(), Error> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o777))
+        .context(path, Phase::Unpacking)
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Get the unix mode of an entry, including the file type bits
+#[cfg(unix)]
+fn unix_mode(metadata: &Metadata) -> u32 {
+    std::os::unix::fs::MetadataExt::mode(metadata)
+}
+
+#[cfg(not(unix))]
+fn unix_mode(metadata: &Metadata) -> u32 {
+    if metadata.is_dir() {
+        zip::MODE_DIRECTORY | 0o755
+    } else if metadata.is_symlink() {
+        zip::MODE_SYMLINK | 0o777
+    } else if metadata.permissions().readonly() {
+        0o100_444
+    } else {
+        0o100_644
+    }
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()).to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().replace('\\', "/").into_bytes()
+}
+
+/// Turn the raw bytes of an archive entry name back into an `OsStr`
+#[cfg(unix)]
+fn os_str(bytes: &[u8]) -> Cow<'_, OsStr> {
+    Cow::Borrowed(std::os::unix::ffi::OsStrExt::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn os_str(bytes: &[u8]) -> Cow<'_, OsStr> {
+    match String::from_utf8_lossy(bytes) {
+        Cow::Borrowed(name) => Cow::Borrowed(OsStr::new(name)),
+        Cow::Owned(name) => Cow::Owned(name.into()),
+    }
+}
+
+/// Get the size of the content of an entry, only files have one
+fn file_size(metadata: &Metadata) -> u64 {
+    if metadata.is_file() {
+        metadata.len()
+    } else {
+        0
+    }
+}
+
+/// Get the modification time of an entry in seconds since the unix epoch
+fn mtime(metadata: &Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// An input with its first bytes already read to detect its format
+type Sniffed<R> = Chain<Cursor<Vec<u8>>, CountingReader<R>>;
+
+/// Read the first bytes of an archive, used to detect its format
+fn read_header<R: Read>(reader: &mut R) -> std::io::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(DETECT_LEN);
+    reader
+        .by_ref()
+        .take(DETECT_LEN as u64)
+        .read_to_end(&mut header)?;
+    Ok(header)
 }
@@ -0,0 +1,221 @@
+use std::fmt;
+
+/// Limits on what an extraction may write, to stop decompression bombs, see [`Extractor::limits`](crate::Extractor::limits)
+///
+/// Nothing is limited by default.
+///
+/// # Example
+///
+/// ```
+/// use comprexor::Limits;
+///
+/// let limits = Limits::new()
+///     .max_total_size(1 << 30)
+///     .max_entries(10_000)
+///     .max_entry_size(100 << 20)
+///     .max_ratio(100)
+///     .max_path_depth(32);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Limits {
+    pub(crate) total_size: Option<u64>,
+    pub(crate) entries: Option<u64>,
+    pub(crate) entry_size: Option<u64>,
+    pub(crate) ratio: Option<u64>,
+    pub(crate) path_depth: Option<usize>,
+}
+
+impl Limits {
+    /// Create limits that do not limit anything yet
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the number of decompressed bytes, see [`ArchiveInfo::output_size`](crate::ArchiveInfo::output_size)
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{Compressor, Error, Extractor, Limit, Limits};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// std::fs::create_dir("./folder").unwrap();
+    /// std::fs::write("./folder/a.txt", vec![b'a'; 6_000]).unwrap();
+    /// std::fs::write("./folder/b.txt", vec![b'b'; 6_000]).unwrap();
+    /// Compressor::new("./folder", "./archive.tar.gz").run().unwrap();
+    ///
+    /// let extractor = Extractor::new("./archive.tar.gz", "./output")
+    ///     .limits(Limits::new().max_total_size(10_000));
+    /// assert!(matches!(
+    ///     extractor.extract(),
+    ///     Err(Error::LimitExceeded { limit: Limit::TotalSize(10_000), .. })
+    /// ));
+    /// ```
+    #[must_use]
+    pub fn max_total_size(mut self, bytes: u64) -> Self {
+        self.total_size = Some(bytes);
+        self
+    }
+
+    /// Limit the number of entries
+    ///
+    /// Every entry read from the archive counts, including the ones that are not selected, skipped
+    /// or rejected. Directories are unpacked at the end of an extraction, so this also bounds
+    /// how many of them are held until then.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{Compressor, Error, Extractor, Limit, Limits};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// for name in ["a", "b", "c", "d"] {
+    ///     std::fs::create_dir_all(format!("./folder/{name}")).unwrap();
+    /// }
+    /// Compressor::new("./folder", "./archive.tar.gz").run().unwrap();
+    ///
+    /// // 5 entries: the folder and the 4 directories inside it
+    /// let extractor = Extractor::new("./archive.tar.gz", "./output")
+    ///     .limits(Limits::new().max_entries(3));
+    /// assert!(matches!(
+    ///     extractor.extract(),
+    ///     Err(Error::LimitExceeded { limit: Limit::Entries(3), .. })
+    /// ));
+    ///
+    /// let extractor = Extractor::new("./archive.tar.gz", "./output")
+    ///     .limits(Limits::new().max_entries(5));
+    /// assert!(extractor.extract().is_ok());
+    /// ```
+    #[must_use]
+    pub fn max_entries(mut self, entries: u64) -> Self {
+        self.entries = Some(entries);
+        self
+    }
+
+    /// Limit the size of each entry
+    ///
+    /// Only the entries that are unpacked are limited, the content of a skipped entry does not
+    /// count towards the size of another one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{Compressor, Error, Extractor, Limit, Limits, OverwritePolicy};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// std::fs::create_dir("./folder").unwrap();
+    /// std::fs::write("./folder/a_small.txt", "ab").unwrap();
+    /// std::fs::write("./folder/b_big.bin", vec![0; 2_000_000]).unwrap();
+    /// Compressor::builder("./folder", "./archive.tar.gz")
+    ///     .reproducible(true)
+    ///     .build()
+    ///     .unwrap()
+    ///     .run()
+    ///     .unwrap();
+    /// let limits = Limits::new().max_entry_size(1_000_000);
+    ///
+    /// let extractor = Extractor::new("./archive.tar.gz", "./output").limits(limits);
+    /// assert!(matches!(
+    ///     extractor.extract(),
+    ///     Err(Error::LimitExceeded { limit: Limit::EntrySize(1_000_000), .. })
+    /// ));
+    ///
+    /// // The big entry is not selected
+    /// Extractor::new("./archive.tar.gz", "./selected")
+    ///     .include("a_small.txt")
+    ///     .limits(limits)
+    ///     .extract()
+    ///     .unwrap();
+    /// assert_eq!(std::fs::read("./selected/folder/a_small.txt").unwrap(), b"ab");
+    ///
+    /// // The big entry already exists and is skipped
+    /// std::fs::create_dir_all("./existing/folder").unwrap();
+    /// std::fs::write("./existing/folder/b_big.bin", "kept").unwrap();
+    /// Extractor::new("./archive.tar.gz", "./existing")
+    ///     .overwrite(OverwritePolicy::Skip)
+    ///     .limits(limits)
+    ///     .extract()
+    ///     .unwrap();
+    /// assert_eq!(std::fs::read("./existing/folder/a_small.txt").unwrap(), b"ab");
+    /// assert_eq!(std::fs::read("./existing/folder/b_big.bin").unwrap(), b"kept");
+    /// ```
+    #[must_use]
+    pub fn max_entry_size(mut self, bytes: u64) -> Self {
+        self.entry_size = Some(bytes);
+        self
+    }
+
+    /// Limit the ratio between the decompressed and the compressed bytes
+    ///
+    /// The ratio is only checked once more than 1 MiB was decompressed, as the
+    /// first bytes of an archive often compress a lot better than the rest.
+    #[must_use]
+    pub fn max_ratio(mut self, ratio: u64) -> Self {
+        self.ratio = Some(ratio);
+        self
+    }
+
+    /// Limit the number of components in the path of an entry
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use comprexor::{Compressor, Error, Extractor, Limit, Limits};
+    ///
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # std::env::set_current_dir(dir.path()).unwrap();
+    /// std::fs::create_dir_all("./folder/a/b").unwrap();
+    /// std::fs::write("./folder/a/b/file.txt", "content").unwrap();
+    /// Compressor::new("./folder", "./archive.tar.gz").run().unwrap();
+    ///
+    /// // `folder/a/b/file.txt` has 4 components
+    /// let extractor = Extractor::new("./archive.tar.gz", "./output")
+    ///     .limits(Limits::new().max_path_depth(3));
+    /// assert!(matches!(
+    ///     extractor.extract(),
+    ///     Err(Error::LimitExceeded { limit: Limit::PathDepth(3), .. })
+    /// ));
+    /// assert!(!std::path::Path::new("./output").exists());
+    /// ```
+    #[must_use]
+    pub fn max_path_depth(mut self, depth: usize) -> Self {
+        self.path_depth = Some(depth);
+        self
+    }
+
+    pub(crate) fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A limit exceeded by an extraction, holding the configured maximum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Limit {
+    /// See [`Limits::max_total_size`]
+    TotalSize(u64),
+    /// See [`Limits::max_entries`]
+    Entries(u64),
+    /// See [`Limits::max_entry_size`]
+    EntrySize(u64),
+    /// See [`Limits::max_ratio`]
+    Ratio(u64),
+    /// See [`Limits::max_path_depth`]
+    PathDepth(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::TotalSize(bytes) => write!(f, "the total size limit of {bytes} bytes"),
+            Limit::Entries(entries) => write!(f, "the limit of {entries} entries"),
+            Limit::EntrySize(bytes) => write!(f, "the entry size limit of {bytes} bytes"),
+            Limit::Ratio(ratio) => write!(f, "the compression ratio limit of {ratio}"),
+            Limit::PathDepth(depth) => write!(f, "the path depth limit of {depth}"),
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use tar::{Archive, EntryType};
+
+use crate::{
+    error::ResultExt,
+    os_str,
+    progress::{Count, Tracked, Tracker},
+    zip::{self, ZipReader},
+    ArchiveInfo, Error, Phase,
+};
+
+/// The entries of an archive and its sizes, returned by [`Extractor::list`](crate::Extractor::list)
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct Listing {
+    entries: Vec<ArchiveEntry>,
+    info: ArchiveInfo,
+}
+
+impl Listing {
+    pub(crate) fn new(entries: Vec<ArchiveEntry>, info: ArchiveInfo) -> Self {
+        Self { entries, info }
+    }
+
+    /// Get the entries in the order they are stored in the archive
+    #[must_use]
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    /// Get the sizes of the archive, the same as an extraction of it would return
+    #[must_use]
+    pub fn info(&self) -> &ArchiveInfo {
+        &self.info
+    }
+}
+
+/// An entry of an archive, as stored in it
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ArchiveEntry {
+    path: PathBuf,
+    kind: EntryKind,
+    size: u64,
+    mode: Option<u32>,
+    mtime: u64,
+    uid: Option<u64>,
+    gid: Option<u64>,
+    link_target: Option<PathBuf>,
+}
+
+impl ArchiveEntry {
+    /// Get the path of the entry inside the archive
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the type of the entry
+    #[must_use]
+    pub fn kind(&self) -> EntryKind {
+        self.kind
+    }
+
+    /// Get the size of the content of the entry, 0 for entries without content
+    #[must_use]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Get the unix permissions of the entry, `None` for zip entries made on other systems
+    #[must_use]
+    pub fn mode(&self) -> Option<u32> {
+        self.mode
+    }
+
+    /// Get the modification time in seconds since the unix epoch
+    #[must_use]
+    pub fn mtime(&self) -> u64 {
+        self.mtime
+    }
+
+    /// Get the id of the owner, `None` for zip entries
+    #[must_use]
+    pub fn uid(&self) -> Option<u64> {
+        self.uid
+    }
+
+    /// Get the id of the group, `None` for zip entries
+    #[must_use]
+    pub fn gid(&self) -> Option<u64> {
+        self.gid
+    }
+
+    /// Get what a symlink or a hard link points to
+    #[must_use]
+    pub fn link_target(&self) -> Option<&Path> {
+        self.link_target.as_deref()
+    }
+}
+
+/// The type of an archive entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    /// A hard link to an earlier entry of the archive
+    HardLink,
+    CharDevice,
+    BlockDevice,
+    Fifo,
+    /// An entry type this crate does not know about
+    Other,
+}
+
+impl From<EntryType> for EntryKind {
+    fn from(entry_type: EntryType) -> Self {
+        match entry_type {
+            EntryType::Regular | EntryType::Continuous => EntryKind::File,
+            EntryType::Directory => EntryKind::Directory,
+            EntryType::Symlink => EntryKind::Symlink,
+            EntryType::Link => EntryKind::HardLink,
+            EntryType::Char => EntryKind::CharDevice,
+            EntryType::Block => EntryKind::BlockDevice,
+            EntryType::Fifo => EntryKind::Fifo,
+            _ => EntryKind::Other,
+        }
+    }
+}
+
+/// Read the entries of a tar archive, their content is skipped
+pub(crate) fn list_tar<R: Read>(
+    archive: &mut Archive<R>,
+    source: &Path,
+    tracker: &Tracker<'_>,
+) -> Result<Vec<ArchiveEntry>, Error> {
+    let mut entries = Vec::new();
+    for entry in archive.entries().context(source, Phase::Decoding)? {
+        let entry = entry.context(source, Phase::Decoding)?;
+        let path = entry.path().context(source, Phase::Decoding)?.into_owned();
+        tracker
+            .read_entry(&path)
+            .and_then(|()| tracker.start_entry(&path))
+            .and_then(|()| tracker.check_entry_size(entry.size()))
+            .context(source, Phase::Decoding)?;
+
+        let header = entry.header();
+        let link_target = entry.link_name().context(source, Phase::Decoding)?;
+        entries.push(ArchiveEntry {
+            kind: header.entry_type().into(),
+            size: entry.size(),
+            mode: header.mode().ok().map(|mode| mode & 0o7777),
+            mtime: header.mtime().unwrap_or(0),
+            uid: header.uid().ok(),
+            gid: header.gid().ok(),
+            link_target: link_target.map(|target| target.into_owned()),
+            path,
+        });
+        tracker.finish_entry();
+    }
+    Ok(entries)
+}
+
+/// The longest symlink target kept while reading the content of zip entries
+const MAX_LINK_TARGET: usize = 4096;
+
+/// Read the entries of a zip archive, returns them with the reader positioned after the central directory
+///
+/// The content of the entries is decompressed to check it and to get its size. The types and
+/// modes are only known from the central directory, so they are filled in at the end.
+pub(crate) fn list_zip<R: BufRead>(
+    mut zip: ZipReader<R>,
+    source: &Path,
+    tracker: &Tracker<'_>,
+) -> Result<(Vec<ArchiveEntry>, R), Error> {
+    let mut entries = Vec::new();
+    // The offsets, and the content of the small entries in case the central directory says they are symlinks
+    let mut stored = Vec::new();
+    while let Some(entry) = zip.next_entry().context(source, Phase::Decoding)? {
+        let path = PathBuf::from(&*os_str(&entry.name));
+        tracker
+            .read_entry(&path)
+            .and_then(|()| tracker.start_entry(&path))
+            .context(source, Phase::Decoding)?;
+
+        let mut content = Tracked::new(Capture::default(), tracker, Count::Written);
+        let size = zip
+            .read_data(&entry, &mut content)
+            .context(source, Phase::Decoding)?;
+        stored.push((entry.offset, content.into_inner().into_data()));
+
+        entries.push(ArchiveEntry {
+            path,
+            kind: if entry.is_dir() {
+                EntryKind::Directory
+            } else {
+                EntryKind::File
+            },
+            size,
+            mode: None,
+            mtime: entry.mtime,
+            uid: None,
+            gid: None,
+            link_target: None,
+        });
+        tracker.finish_entry();
+    }
+
+    let (central, reader) = zip.central_directory().context(source, Phase::Decoding)?;
+    let modes: HashMap<_, _> = central
+        .into_iter()
+        .filter_map(|entry| Some((entry.offset, entry.mode?)))
+        .collect();
+    for (entry, (offset, content)) in entries.iter_mut().zip(stored) {
+        let Some(&mode) = modes.get(&offset) else {
+            continue;
+        };
+        entry.mode = Some(mode & 0o7777);
+        match mode & zip::MODE_TYPE_MASK {
+            zip::MODE_DIRECTORY => entry.kind = EntryKind::Directory,
+            zip::MODE_SYMLINK => {
+                entry.kind = EntryKind::Symlink;
+                entry.link_target = content.map(|target| PathBuf::from(&*os_str(&target)));
+            }
+            _ => {}
+        }
+    }
+    Ok((entries, reader))
+}
+
+/// A writer keeping what is written to it as long as it is short enough to be a symlink target
+#[derive(Default)]
+struct Capture {
+    data: Vec<u8>,
+    overflow: bool,
+}
+
+impl Capture {
+    fn into_data(self) -> Option<Vec<u8>> {
+        (!self.overflow).then_some(self.data)
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.data.len() + buf.len() > MAX_LINK_TARGET {
+            self.overflow = true;
+            self.data = Vec::new();
+        } else if !self.overflow {
+            self.data.extend_from_slice(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
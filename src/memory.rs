@@ -0,0 +1,144 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{BufRead, Read},
+    path::{Path, PathBuf},
+};
+
+use tar::{Archive, EntryType};
+
+use crate::{
+    error::ResultExt,
+    os_str,
+    progress::{Count, Tracked, Tracker},
+    select::Selection,
+    strip_path,
+    walk::{is_plain, normalize},
+    zip::{self, ZipReader},
+    Error, Phase,
+};
+
+/// A file added from memory with [`CompressorBuilder::add_bytes`](crate::CompressorBuilder::add_bytes)
+#[derive(Debug, Clone, PartialEq, PartialOrd, Hash)]
+pub(crate) struct MemoryFile {
+    /// Path of the file inside the archive
+    pub(crate) name: PathBuf,
+    pub(crate) content: Vec<u8>,
+    /// Unix permissions, without the file type bits
+    pub(crate) mode: u32,
+}
+
+/// The files of an archive by their path, see [`Extractor::extract_to_map`](crate::Extractor::extract_to_map)
+pub(crate) type FileMap = BTreeMap<PathBuf, Vec<u8>>;
+
+/// Get the key of the entry at `name` in a [`FileMap`], `None` when it is stripped away or not selected
+///
+/// An absolute path or a `..` component fails with [`Error::PathTraversal`], dropping them
+/// would give the entry the key of another one.
+fn map_key(
+    name: &Path,
+    is_dir: bool,
+    selection: Option<&Selection>,
+    strip: usize,
+) -> Result<Option<PathBuf>, Error> {
+    if selection.is_some_and(|selection| !selection.matches(name, is_dir)) {
+        return Ok(None);
+    }
+    let Some(path) = strip_path(name, strip) else {
+        return Ok(None);
+    };
+    if !is_plain(&path) {
+        return Err(Error::PathTraversal {
+            path: name.to_path_buf(),
+        });
+    }
+    Ok(Some(normalize(&path)))
+}
+
+/// Read the content of the regular files of a tar archive into memory
+pub(crate) fn read_tar_files<R: Read>(
+    archive: &mut Archive<R>,
+    source: &Path,
+    selection: Option<&Selection>,
+    strip: usize,
+    tracker: &Tracker<'_>,
+) -> Result<FileMap, Error> {
+    let mut files = FileMap::new();
+    for entry in archive.entries().context(source, Phase::Decoding)? {
+        let mut entry = entry.context(source, Phase::Decoding)?;
+        let is_file = matches!(
+            entry.header().entry_type(),
+            EntryType::Regular | EntryType::Continuous
+        );
+        let name = entry.path().context(source, Phase::Decoding)?.into_owned();
+        tracker.read_entry(&name).context(source, Phase::Decoding)?;
+        let Some(key) = map_key(&name, !is_file, selection, strip)?.filter(|_| is_file) else {
+            continue;
+        };
+
+        tracker
+            .start_entry(&name)
+            .and_then(|()| tracker.check_entry_size(entry.size()))
+            .context(source, Phase::Decoding)?;
+        let mut content = Tracked::new(Vec::new(), tracker, Count::Written);
+        std::io::copy(&mut entry, &mut content).context(source, Phase::Unpacking)?;
+        files.insert(key, content.into_inner());
+        tracker.finish_entry();
+    }
+    Ok(files)
+}
+
+/// Read the content of the regular files of a zip archive into memory, returns them with
+/// the reader positioned after the central directory
+///
+/// Symlinks are only known from the central directory, they are removed at the end.
+pub(crate) fn read_zip_files<R: BufRead>(
+    mut zip: ZipReader<R>,
+    source: &Path,
+    selection: Option<&Selection>,
+    strip: usize,
+    tracker: &Tracker<'_>,
+) -> Result<(FileMap, R), Error> {
+    let mut files = FileMap::new();
+    // The local header each file got its content from, a name may be stored more than once
+    let mut offsets = HashMap::new();
+    while let Some(entry) = zip.next_entry().context(source, Phase::Decoding)? {
+        let name = PathBuf::from(&*os_str(&entry.name));
+        tracker.read_entry(&name).context(source, Phase::Decoding)?;
+        let key = map_key(&name, entry.is_dir(), selection, strip)?.filter(|_| !entry.is_dir());
+        let Some(key) = key else {
+            zip.read_data(&entry, &mut std::io::sink())
+                .context(source, Phase::Decoding)?;
+            continue;
+        };
+
+        tracker
+            .start_entry(&name)
+            .context(source, Phase::Decoding)?;
+        let mut content = Tracked::new(Vec::new(), tracker, Count::Written);
+        zip.read_data(&entry, &mut content)
+            .context(source, Phase::Unpacking)?;
+        files.insert(key.clone(), content.into_inner());
+        offsets.insert(key, entry.offset);
+        tracker.finish_entry();
+    }
+
+    let (central, reader) = zip.central_directory().context(source, Phase::Decoding)?;
+    let not_files: HashSet<u64> = central
+        .iter()
+        .filter(|entry| {
+            entry.mode.is_some_and(|mode| {
+                matches!(
+                    mode & zip::MODE_TYPE_MASK,
+                    zip::MODE_SYMLINK | zip::MODE_DIRECTORY
+                )
+            })
+        })
+        .map(|entry| entry.offset)
+        .collect();
+    files.retain(|key, _| {
+        !offsets
+            .get(key)
+            .is_some_and(|offset| not_files.contains(offset))
+    });
+    Ok((files, reader))
+}
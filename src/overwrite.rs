@@ -0,0 +1,129 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::Error;
+
+/// What an extraction does with an entry that would be written where something already exists,
+/// see [`Extractor::overwrite`](crate::Extractor::overwrite)
+///
+/// Directories are merged with the existing ones, they are never a conflict. An existing
+/// directory is never replaced by a file: [`Overwrite`](Self::Overwrite) and
+/// [`KeepNewer`](Self::KeepNewer) skip the entry and report it as [`Resolution::Skipped`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum OverwritePolicy {
+    /// Replace what exists with the entry, unless it is a directory
+    #[default]
+    Overwrite,
+    /// Leave what exists untouched and skip the entry
+    Skip,
+    /// Stop the extraction with [`Error::OutputExists`]
+    Fail,
+    /// Replace what exists only if it was modified before the entry
+    KeepNewer,
+    /// Write the entry next to what exists, with a number added to its name: `file.txt` becomes `file.1.txt`
+    Rename,
+}
+
+/// An entry that would have been written where something already existed, see [`ArchiveInfo::conflicts`](crate::ArchiveInfo::conflicts)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Conflict {
+    path: PathBuf,
+    resolution: Resolution,
+}
+
+impl Conflict {
+    /// Get the path that already existed in the output directory
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get what was done with the entry
+    #[must_use]
+    pub fn resolution(&self) -> &Resolution {
+        &self.resolution
+    }
+}
+
+/// What was done with an entry in [`Conflict`] with an existing path
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Resolution {
+    /// The existing path was replaced by the entry
+    Overwritten,
+    /// The entry was skipped
+    Skipped,
+    /// The entry was written at another path
+    Renamed(PathBuf),
+}
+
+/// Decide where an entry modified at `mtime` is written, when `path` may already exist
+///
+/// Returns `None` when the entry is skipped, the conflicts are added to `conflicts`.
+/// An existing directory is never replaced, the entry is skipped instead.
+pub(crate) fn resolve(
+    policy: OverwritePolicy,
+    path: &Path,
+    mtime: u64,
+    conflicts: &mut Vec<Conflict>,
+) -> Result<Option<PathBuf>, Error> {
+    let Ok(existing) = path.symlink_metadata() else {
+        return Ok(Some(path.to_path_buf()));
+    };
+    let (resolution, target) = match policy {
+        OverwritePolicy::Overwrite | OverwritePolicy::KeepNewer if existing.is_dir() => {
+            (Resolution::Skipped, None)
+        }
+        OverwritePolicy::Overwrite => (Resolution::Overwritten, Some(path.to_path_buf())),
+        OverwritePolicy::Skip => (Resolution::Skipped, None),
+        OverwritePolicy::Fail => {
+            return Err(Error::OutputExists {
+                path: path.to_path_buf(),
+            })
+        }
+        OverwritePolicy::KeepNewer => {
+            let existing_mtime = existing
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |duration| duration.as_secs());
+            if existing_mtime > mtime {
+                (Resolution::Skipped, None)
+            } else {
+                (Resolution::Overwritten, Some(path.to_path_buf()))
+            }
+        }
+        OverwritePolicy::Rename => {
+            let renamed = free_name(path);
+            (Resolution::Renamed(renamed.clone()), Some(renamed))
+        }
+    };
+    conflicts.push(Conflict {
+        path: path.to_path_buf(),
+        resolution,
+    });
+    Ok(target)
+}
+
+/// Find the first `name.N.extension` next to `path` that does not exist
+fn free_name(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default();
+    let mut number = 1u64;
+    loop {
+        let mut name = OsString::from(stem);
+        name.push(format!(".{number}"));
+        if let Some(extension) = path.extension() {
+            name.push(".");
+            name.push(extension);
+        }
+        let candidate = path.with_file_name(name);
+        if candidate.symlink_metadata().is_err() {
+            return candidate;
+        }
+        number += 1;
+    }
+}
@@ -0,0 +1,488 @@
+use flate2::{
+    read::MultiGzDecoder, Compress, Compression, Crc, Decompress, FlushCompress, FlushDecompress,
+    Status,
+};
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+/// Size of the blocks the input is split into, the same as pigz
+const BLOCK_SIZE: usize = 128 * 1024;
+
+/// Size of the deflate window, the end of each block is the dictionary of the next one
+const DICTIONARY_SIZE: usize = 32 * 1024;
+
+/// A task run by a [`Pool`]
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Threads running jobs in the order they are given
+struct Pool {
+    jobs: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Pool {
+    fn new(threads: usize) -> Self {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..threads)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                thread::spawn(move || loop {
+                    // The lock is released before running the job, so the other workers can take the next one
+                    let job = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => return,
+                    };
+                    let Ok(job) = job else {
+                        return;
+                    };
+                    job();
+                })
+            })
+            .collect();
+        Self {
+            jobs: Some(jobs),
+            workers,
+        }
+    }
+
+    /// Run `task` on one of the threads, its result is sent to the returned receiver
+    fn run<T: Send + 'static>(
+        &self,
+        task: impl FnOnce() -> io::Result<T> + Send + 'static,
+    ) -> io::Result<Receiver<io::Result<T>>> {
+        let (result, receiver) = mpsc::channel();
+        let job: Job = Box::new(move || {
+            // The receiver may have been dropped after an error
+            let _ = result.send(task());
+        });
+        self.jobs
+            .as_ref()
+            .and_then(|jobs| jobs.send(job).ok())
+            .ok_or_else(|| io::Error::other("the worker threads stopped"))?;
+        Ok(receiver)
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Wait for the result of a job run by a [`Pool`]
+fn wait<T>(receiver: &Receiver<io::Result<T>>) -> io::Result<T> {
+    receiver
+        .recv()
+        .map_err(|_| io::Error::other("a worker thread panicked"))?
+}
+
+/// A gzip encoder that deflates blocks of its input on several threads, like pigz
+///
+/// Each block is deflated with the end of the previous block as its dictionary and ends
+/// with a sync flush, so the blocks put together are a single deflate stream. The output
+/// is a regular gzip file that any decoder can read.
+pub(crate) struct ParallelGzEncoder<W: Write> {
+    writer: W,
+    level: Compression,
+    /// The input not sent to a worker yet
+    block: Vec<u8>,
+    dictionary: Vec<u8>,
+    crc: Crc,
+    header_written: bool,
+    /// The compressed blocks not written yet, in order
+    pending: VecDeque<Receiver<io::Result<Vec<u8>>>>,
+    threads: usize,
+    pool: Pool,
+}
+
+impl<W: Write> ParallelGzEncoder<W> {
+    pub(crate) fn new(writer: W, level: Compression, threads: usize) -> Self {
+        Self {
+            writer,
+            level,
+            block: Vec::with_capacity(BLOCK_SIZE),
+            dictionary: Vec::new(),
+            crc: Crc::new(),
+            header_written: false,
+            pending: VecDeque::new(),
+            threads,
+            pool: Pool::new(threads),
+        }
+    }
+
+    /// Write the remaining compressed data and the gzip trailer, and return the underlying writer
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        self.send_block(true)?;
+        self.write_pending(0)?;
+        self.writer.write_all(&self.crc.sum().to_le_bytes())?;
+        self.writer.write_all(&self.crc.amount().to_le_bytes())?;
+        Ok(self.writer)
+    }
+
+    /// Send the current block to the workers, the last block ends the deflate stream
+    fn send_block(&mut self, last: bool) -> io::Result<()> {
+        if !self.header_written {
+            self.writer.write_all(&gzip_header(self.level))?;
+            self.header_written = true;
+        }
+
+        let data = std::mem::replace(&mut self.block, Vec::with_capacity(BLOCK_SIZE));
+        let dictionary = std::mem::replace(
+            &mut self.dictionary,
+            data[data.len().saturating_sub(DICTIONARY_SIZE)..].to_vec(),
+        );
+        let level = self.level;
+        let receiver = self
+            .pool
+            .run(move || deflate_block(&data, &dictionary, level, last))?;
+        self.pending.push_back(receiver);
+
+        // Bound the memory used by the blocks waiting to be written
+        self.write_pending(self.threads * 2)
+    }
+
+    /// Write the compressed blocks in order until at most `keep` are left pending
+    fn write_pending(&mut self, keep: usize) -> io::Result<()> {
+        while self.pending.len() > keep {
+            let Some(receiver) = self.pending.pop_front() else {
+                break;
+            };
+            let compressed = wait(&receiver)?;
+            self.writer.write_all(&compressed)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ParallelGzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..len]);
+        self.crc.update(&buf[..len]);
+        if self.block.len() == BLOCK_SIZE {
+            self.send_block(false)?;
+        }
+        Ok(len)
+    }
+
+    /// Compress what was written so far, ending it with a sync flush like a single threaded encoder
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.block.is_empty() {
+            self.send_block(false)?;
+        }
+        self.write_pending(0)?;
+        self.writer.flush()
+    }
+}
+
+/// Size of the fixed part of a BGZF member header, up to the length of its extra field
+const BGZF_HEADER_LEN: usize = 12;
+
+/// Largest decompressed size of a BGZF member
+const MAX_MEMBER_DATA: usize = 64 * 1024;
+
+const FLAG_TEXT: u8 = 0x01;
+const FLAG_EXTRA: u8 = 0x04;
+
+/// A gzip decoder that inflates the members of a BGZF file on several threads
+///
+/// BGZF, written by `bgzip`, is a multi-member gzip file where the extra field of every
+/// member holds its compressed size. The members are independent and can be read ahead
+/// without decompressing them, which is what lets them be inflated at the same time.
+/// From the first member that is not BGZF on, the rest of the stream is decompressed on
+/// the calling thread like any multi-member gzip file.
+pub(crate) struct ParallelGzDecoder<R: Read> {
+    /// Only `None` while the reader moves to the sequential decoder
+    input: Option<Input<R>>,
+    /// The decompressed member being read
+    current: io::Cursor<Vec<u8>>,
+    /// The decompressed members not read yet, in order
+    pending: VecDeque<Receiver<io::Result<Vec<u8>>>>,
+    /// What was read of the first member that is not BGZF
+    rest: Option<Vec<u8>>,
+    /// The end of the BGZF members was reached
+    done: bool,
+    threads: usize,
+    pool: Pool,
+}
+
+/// Where a [`ParallelGzDecoder`] reads its members from
+enum Input<R: Read> {
+    /// BGZF members, inflated by the workers
+    Members(R),
+    /// The members from the first one that is not BGZF, inflated on the calling thread
+    Sequential(Box<MultiGzDecoder<io::Chain<io::Cursor<Vec<u8>>, R>>>),
+}
+
+impl<R: Read> ParallelGzDecoder<R> {
+    pub(crate) fn new(reader: R, threads: usize) -> Self {
+        Self {
+            input: Some(Input::Members(reader)),
+            current: io::Cursor::new(Vec::new()),
+            pending: VecDeque::new(),
+            rest: None,
+            done: false,
+            threads,
+            pool: Pool::new(threads),
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> &R {
+        match &self.input {
+            Some(Input::Members(reader)) => reader,
+            Some(Input::Sequential(decoder)) => decoder.get_ref().get_ref().1,
+            None => unreachable!("the reader is only moved inside of `read`"),
+        }
+    }
+
+    /// Send members to the workers until enough of them are pending or the BGZF members end
+    fn read_ahead(&mut self) -> io::Result<()> {
+        let Some(Input::Members(reader)) = &mut self.input else {
+            return Ok(());
+        };
+        while !self.done && self.pending.len() < self.threads * 2 {
+            let member = match read_member(reader) {
+                Ok(Some(Member::Bgzf(member))) => member,
+                Ok(Some(Member::Other(start))) => {
+                    self.done = true;
+                    self.rest = Some(start);
+                    break;
+                }
+                Ok(None) => {
+                    self.done = true;
+                    break;
+                }
+                // The members read before are still returned first
+                Err(err) => {
+                    self.done = true;
+                    let (result, receiver) = mpsc::channel();
+                    let _ = result.send(Err(err));
+                    self.pending.push_back(receiver);
+                    break;
+                }
+            };
+            let receiver = self.pool.run(move || inflate_member(&member))?;
+            self.pending.push_back(receiver);
+        }
+        Ok(())
+    }
+
+    /// Give the rest of the stream to a sequential decoder, once the BGZF members before it were read
+    fn read_rest(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(start) = self.rest.take() {
+            if let Some(Input::Members(reader)) = self.input.take() {
+                let rest = io::Cursor::new(start).chain(reader);
+                self.input = Some(Input::Sequential(Box::new(MultiGzDecoder::new(rest))));
+            }
+        }
+        match &mut self.input {
+            Some(Input::Sequential(decoder)) => decoder.read(buf),
+            _ => Ok(0),
+        }
+    }
+}
+
+impl<R: Read> Read for ParallelGzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let len = self.current.read(buf)?;
+            if len > 0 || buf.is_empty() {
+                return Ok(len);
+            }
+            self.read_ahead()?;
+            let Some(receiver) = self.pending.pop_front() else {
+                return self.read_rest(buf);
+            };
+            self.current = io::Cursor::new(wait(&receiver)?);
+        }
+    }
+}
+
+/// Check if `header`, the start of a file, is the header of a BGZF member
+pub(crate) fn is_bgzf(header: &[u8]) -> bool {
+    let Some((fixed, rest)) = header.split_first_chunk::<BGZF_HEADER_LEN>() else {
+        return false;
+    };
+    bgzf_extra_len(*fixed)
+        .and_then(|len| rest.get(..len))
+        .and_then(block_size)
+        .is_some()
+}
+
+/// Get the length of the extra field of a member from the fixed part of its header,
+/// `None` if it is not a gzip member with only an extra field before its data
+fn bgzf_extra_len(header: [u8; BGZF_HEADER_LEN]) -> Option<usize> {
+    // The text flag does not change the layout of the header
+    let only_extra = header[3] & !FLAG_TEXT == FLAG_EXTRA;
+    (header[..3] == [0x1f, 0x8b, 8] && only_extra)
+        .then(|| usize::from(u16::from_le_bytes([header[10], header[11]])))
+}
+
+/// Find the size of a member, less one, in its extra field
+fn block_size(mut extra: &[u8]) -> Option<usize> {
+    while let [first, second, len_low, len_high, rest @ ..] = extra {
+        let len = usize::from(u16::from_le_bytes([*len_low, *len_high]));
+        let data = rest.get(..len)?;
+        if [*first, *second] == *b"BC" && len == 2 {
+            return Some(usize::from(u16::from_le_bytes([data[0], data[1]])));
+        }
+        extra = &rest[len..];
+    }
+    None
+}
+
+/// A member read by [`read_member`]
+enum Member {
+    /// The deflate data of a BGZF member followed by its 8 bytes trailer
+    Bgzf(Vec<u8>),
+    /// The bytes read from the start of a member that is not BGZF
+    Other(Vec<u8>),
+}
+
+/// Read the next member from `reader`, `None` at the end of the input
+fn read_member<R: Read>(reader: &mut R) -> io::Result<Option<Member>> {
+    let mut start = Vec::with_capacity(BGZF_HEADER_LEN);
+    reader
+        .take(BGZF_HEADER_LEN as u64)
+        .read_to_end(&mut start)?;
+    if start.is_empty() {
+        return Ok(None);
+    }
+    let Some(extra_len) = <[u8; BGZF_HEADER_LEN]>::try_from(start.as_slice())
+        .ok()
+        .and_then(bgzf_extra_len)
+    else {
+        return Ok(Some(Member::Other(start)));
+    };
+
+    let mut extra = vec![0; extra_len];
+    reader.read_exact(&mut extra)?;
+    let Some(block_size) = block_size(&extra) else {
+        start.extend_from_slice(&extra);
+        return Ok(Some(Member::Other(start)));
+    };
+    // The deflate data and the trailer are what is left of the member
+    let rest_len = (block_size + 1)
+        .checked_sub(BGZF_HEADER_LEN + extra_len + 8)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "corrupt BGZF member header"))?
+        + 8;
+    let mut rest = vec![0; rest_len];
+    reader.read_exact(&mut rest)?;
+    Ok(Some(Member::Bgzf(rest)))
+}
+
+/// Inflate the deflate data of a member and check it against its trailer
+fn inflate_member(member: &[u8]) -> io::Result<Vec<u8>> {
+    let corrupt = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let (data, trailer) = member.split_at(member.len() - 8);
+    let expected_crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let expected_len = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+
+    let mut decompress = Decompress::new(false);
+    // Stop as soon as the member inflates to more than its trailer says or a BGZF member
+    // holds, the room for one more byte is what lets the end of the stream be reached
+    let limit = (expected_len as usize).min(MAX_MEMBER_DATA);
+    let mut output = Vec::with_capacity(limit + 1);
+    loop {
+        let progress = (decompress.total_in(), decompress.total_out());
+        let consumed = decompress.total_in() as usize;
+        let status = decompress
+            .decompress_vec(&data[consumed..], &mut output, FlushDecompress::Finish)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        if output.len() > limit {
+            return Err(corrupt("BGZF member inflates to more than its size"));
+        }
+        if status == Status::StreamEnd {
+            break;
+        }
+        if (decompress.total_in(), decompress.total_out()) == progress {
+            return Err(corrupt("truncated deflate stream"));
+        }
+    }
+
+    let mut crc = Crc::new();
+    crc.update(&output);
+    if crc.sum() != expected_crc || crc.amount() != expected_len {
+        return Err(corrupt(
+            "corrupt gzip stream does not have a matching checksum",
+        ));
+    }
+    Ok(output)
+}
+
+/// Deflate a block with a preset `dictionary`, the last block ends the stream and the
+/// other ones end with a sync flush so the next block starts on a byte boundary
+///
+/// flate2 only sets dictionaries with a zlib backend. Deflating the dictionary first and
+/// dropping its output primes the window the same way, the decoder has the dictionary in
+/// its window too since it is the end of the previous block.
+fn deflate_block(
+    data: &[u8],
+    dictionary: &[u8],
+    level: Compression,
+    last: bool,
+) -> io::Result<Vec<u8>> {
+    let mut compress = Compress::new(level, false);
+    let mut output = Vec::with_capacity(data.len() / 2 + 1024);
+    if !dictionary.is_empty() {
+        deflate_into(&mut compress, dictionary, &mut output, FlushCompress::Sync)?;
+        output.clear();
+    }
+    let flush = if last {
+        FlushCompress::Finish
+    } else {
+        FlushCompress::Sync
+    };
+    deflate_into(&mut compress, data, &mut output, flush)?;
+    Ok(output)
+}
+
+/// Deflate all of `data` into `output`, ending with `flush`
+fn deflate_into(
+    compress: &mut Compress,
+    data: &[u8],
+    output: &mut Vec<u8>,
+    flush: FlushCompress,
+) -> io::Result<()> {
+    let start = compress.total_in();
+    loop {
+        if output.capacity() - output.len() < 1024 {
+            output.reserve(output.capacity());
+        }
+        let consumed = (compress.total_in() - start) as usize;
+        let status = compress
+            .compress_vec(&data[consumed..], output, flush)
+            .map_err(io::Error::other)?;
+        let done = if flush == FlushCompress::Finish {
+            status == Status::StreamEnd
+        } else {
+            // The flush is complete once the output was not filled up
+            (compress.total_in() - start) as usize == data.len() && output.len() < output.capacity()
+        };
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+/// The gzip header written by flate2, so both encoders give the same header
+fn gzip_header(level: Compression) -> [u8; 10] {
+    let extra_flags = match level.level() {
+        9.. => 2,
+        1 => 4,
+        _ => 0,
+    };
+    // Magic, deflate, no flags, no modification time, extra flags, unknown operating system
+    [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, extra_flags, 255]
+}
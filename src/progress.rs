@@ -0,0 +1,351 @@
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+    hash::{Hash, Hasher},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{error::Interrupt, CancellationToken, Limit, Limits};
+
+/// The progress of a compression or an extraction, given to the callback set with
+/// [`CompressorBuilder::on_progress`](crate::CompressorBuilder::on_progress) or [`Extractor::on_progress`](crate::Extractor::on_progress)
+///
+/// The byte counts follow the meaning of [`ArchiveInfo`](crate::ArchiveInfo): once the operation
+/// is done, `bytes_read` is the input size and `bytes_written` the output size.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Progress {
+    bytes_read: u64,
+    bytes_written: u64,
+    total_bytes: Option<u64>,
+    entry: Option<PathBuf>,
+    entries_processed: u64,
+}
+
+impl Progress {
+    /// Get the number of bytes read so far: the uncompressed data when compressing,
+    /// the archive when extracting
+    #[must_use]
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Get the number of bytes written so far: the archive when compressing,
+    /// the uncompressed data when extracting
+    #[must_use]
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Get an estimate of what [`Progress::bytes_read`] will be at the end, if known
+    ///
+    /// When compressing it is computed from the size of the inputs, when extracting
+    /// it is the size of the archive file. It is unknown when extracting from a reader.
+    #[must_use]
+    pub fn total_bytes(&self) -> Option<u64> {
+        self.total_bytes
+    }
+
+    /// Get the path inside the archive of the entry being processed
+    #[must_use]
+    pub fn entry(&self) -> Option<&Path> {
+        self.entry.as_deref()
+    }
+
+    /// Get the number of entries fully processed
+    #[must_use]
+    pub fn entries_processed(&self) -> u64 {
+        self.entries_processed
+    }
+}
+
+/// A progress callback, compared and hashed by identity so the types holding it can derive those traits
+#[derive(Clone)]
+pub(crate) struct ProgressCallback(Arc<dyn Fn(&Progress) + Send + Sync>);
+
+impl ProgressCallback {
+    pub(crate) fn new<F: Fn(&Progress) + Send + Sync + 'static>(callback: F) -> Self {
+        Self(Arc::new(callback))
+    }
+
+    fn address(&self) -> *const () {
+        Arc::as_ptr(&self.0).cast()
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ProgressCallback")
+            .field(&self.address())
+            .finish()
+    }
+}
+
+impl PartialEq for ProgressCallback {
+    fn eq(&self, other: &Self) -> bool {
+        self.address() == other.address()
+    }
+}
+
+impl PartialOrd for ProgressCallback {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.address().partial_cmp(&other.address())
+    }
+}
+
+impl Hash for ProgressCallback {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.address().hash(state);
+    }
+}
+
+/// Keeps the progress of an operation up to date and reports it to the callback, if any
+///
+/// It also checks the cancellation token and the limits of an extraction. When the
+/// extraction can be stopped by them, it remembers the paths it created so they can be removed.
+pub(crate) struct Tracker<'a> {
+    callback: Option<&'a ProgressCallback>,
+    cancel: Option<&'a CancellationToken>,
+    limits: Limits,
+    progress: RefCell<Progress>,
+    /// The bytes written for the current entry
+    entry_written: Cell<u64>,
+    /// An entry is being written, the bytes written in between entries are not part of any of them
+    in_entry: Cell<bool>,
+    /// The entries read from the archive, including the ones that were not unpacked
+    entries_read: Cell<u64>,
+    created: RefCell<Vec<PathBuf>>,
+}
+
+/// The ratio limit is only checked after this many bytes were written
+const RATIO_GRACE: u64 = 1 << 20;
+
+impl<'a> Tracker<'a> {
+    pub(crate) fn new(
+        callback: Option<&'a ProgressCallback>,
+        cancel: Option<&'a CancellationToken>,
+        total_bytes: Option<u64>,
+    ) -> Self {
+        Self {
+            callback,
+            cancel,
+            limits: Limits::default(),
+            progress: RefCell::new(Progress {
+                total_bytes,
+                ..Progress::default()
+            }),
+            entry_written: Cell::new(0),
+            in_entry: Cell::new(false),
+            entries_read: Cell::new(0),
+            created: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Fail if the operation was cancelled
+    pub(crate) fn check(&self) -> io::Result<()> {
+        self.cancel.map_or(Ok(()), CancellationToken::check)
+    }
+
+    /// Count an entry read from an archive, fails if the operation was cancelled or the entry goes
+    /// over the entries or path depth limits
+    ///
+    /// Every entry is counted as soon as it is read, whether it is unpacked, skipped or rejected.
+    pub(crate) fn read_entry(&self, path: &Path) -> io::Result<()> {
+        self.check()?;
+        let limits = &self.limits;
+        self.entries_read.set(self.entries_read.get() + 1);
+        if let Some(max) = limits.entries {
+            if self.entries_read.get() > max {
+                return Err(Interrupt::LimitExceeded(Limit::Entries(max)).into());
+            }
+        }
+        if let Some(max) = limits.path_depth {
+            let depth = path
+                .components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .count();
+            if depth > max {
+                return Err(Interrupt::LimitExceeded(Limit::PathDepth(max)).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Start working on an entry, fails if the operation was cancelled
+    ///
+    /// The bytes written until [`Tracker::finish_entry`] count towards the size of this entry.
+    pub(crate) fn start_entry(&self, path: &Path) -> io::Result<()> {
+        self.check()?;
+        self.entry_written.set(0);
+        self.in_entry.set(true);
+        self.update(|progress| progress.entry = Some(path.to_path_buf()));
+        Ok(())
+    }
+
+    /// Fail if the size an entry claims to have goes over the limits
+    pub(crate) fn check_entry_size(&self, size: u64) -> io::Result<()> {
+        match self.limits.entry_size {
+            Some(max) if size > max => Err(Interrupt::LimitExceeded(Limit::EntrySize(max)).into()),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn finish_entry(&self) {
+        self.in_entry.set(false);
+        self.update(|progress| progress.entries_processed += 1);
+    }
+
+    fn add_bytes(&self, count: Count, bytes: u64) -> io::Result<()> {
+        match count {
+            Count::Read => self.update(|progress| progress.bytes_read += bytes),
+            Count::Written => {
+                if self.in_entry.get() {
+                    self.entry_written.set(self.entry_written.get() + bytes);
+                }
+                self.update(|progress| progress.bytes_written += bytes);
+                self.check_written()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_written(&self) -> io::Result<()> {
+        let progress = self.progress.borrow();
+        let limits = &self.limits;
+        let exceeded = match (limits.total_size, limits.entry_size, limits.ratio) {
+            (Some(max), _, _) if progress.bytes_written > max => Limit::TotalSize(max),
+            (_, Some(max), _) if self.in_entry.get() && self.entry_written.get() > max => {
+                Limit::EntrySize(max)
+            }
+            (_, _, Some(max))
+                if progress.bytes_written > RATIO_GRACE
+                    && progress.bytes_written > progress.bytes_read.saturating_mul(max) =>
+            {
+                Limit::Ratio(max)
+            }
+            _ => return Ok(()),
+        };
+        Err(Interrupt::LimitExceeded(exceeded).into())
+    }
+
+    /// Remember what is created when writing `path`, its first missing ancestor inside `root`
+    pub(crate) fn will_create(&self, path: &Path, root: &Path) {
+        if self.cancel.is_none() && self.limits.is_unlimited() {
+            return;
+        }
+        let missing = path
+            .ancestors()
+            .take_while(|ancestor| {
+                *ancestor != root
+                    && ancestor.starts_with(root)
+                    && ancestor.symlink_metadata().is_err()
+            })
+            .last();
+        if let Some(missing) = missing {
+            self.created.borrow_mut().push(missing.to_path_buf());
+        }
+    }
+
+    /// Remove what the extraction created, on a best effort basis
+    pub(crate) fn remove_created(&self) {
+        for path in self.created.borrow_mut().drain(..).rev() {
+            let _ = match path.symlink_metadata() {
+                Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(&path),
+                Ok(_) => std::fs::remove_file(&path),
+                Err(_) => continue,
+            };
+        }
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Progress)) {
+        let mut progress = self.progress.borrow_mut();
+        change(&mut progress);
+        if let Some(callback) = self.callback {
+            (callback.0)(&progress);
+        }
+    }
+}
+
+/// Which count of the [`Progress`] the bytes going through a [`Tracked`] stream add to
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Count {
+    Read,
+    Written,
+}
+
+/// A reader or a writer that reports the bytes going through it to a [`Tracker`],
+/// and stops when the operation is cancelled or goes over its limits
+///
+/// Bytes read again after seeking back are only counted once, seekable streams must start at position 0
+pub(crate) struct Tracked<'t, T> {
+    inner: T,
+    tracker: &'t Tracker<'t>,
+    count: Count,
+    position: u64,
+    reported: u64,
+}
+
+impl<'t, T> Tracked<'t, T> {
+    pub(crate) fn new(inner: T, tracker: &'t Tracker<'t>, count: Count) -> Self {
+        Self {
+            inner,
+            tracker,
+            count,
+            position: 0,
+            reported: 0,
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub(crate) fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn advance(&mut self, bytes: usize) -> io::Result<()> {
+        self.position += bytes as u64;
+        if self.position > self.reported {
+            let new = self.position - self.reported;
+            self.reported = self.position;
+            self.tracker.add_bytes(self.count, new)?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Tracked<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.tracker.check()?;
+        let read = self.inner.read(buf)?;
+        self.advance(read)?;
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for Tracked<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = self.inner.seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+impl<W: Write> Write for Tracked<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tracker.check()?;
+        let written = self.inner.write(buf)?;
+        self.advance(written)?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    io::{BufRead, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use tar::{Archive, EntryType};
+
+use crate::{
+    error::ResultExt,
+    glob::{self, Glob},
+    os_str,
+    progress::{Count, Tracked, Tracker},
+    zip::ZipReader,
+    Error, Phase,
+};
+
+/// The entries an extraction is limited to, see [`Extractor::include`](crate::Extractor::include)
+/// and [`Extractor::include_path`](crate::Extractor::include_path)
+#[derive(Debug)]
+pub(crate) struct Selection {
+    paths: Vec<Vec<String>>,
+    globs: Vec<Glob>,
+}
+
+impl Selection {
+    /// Compile the selection, `None` when every entry is extracted
+    pub(crate) fn new(paths: &[PathBuf], patterns: &[String]) -> Result<Option<Self>, Error> {
+        if paths.is_empty() && patterns.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            paths: paths.iter().map(|path| components(path)).collect(),
+            globs: glob::compile("include", patterns)?,
+        }))
+    }
+
+    /// Check if the entry at `path` was selected, or is inside a selected directory
+    pub(crate) fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        let path = components(path);
+        (1..=path.len()).any(|len| {
+            let prefix = &path[..len];
+            let prefix_is_dir = is_dir || len < path.len();
+            self.paths.iter().any(|selected| selected == prefix)
+                || self
+                    .globs
+                    .iter()
+                    .any(|glob| glob.matches(prefix, prefix_is_dir))
+        })
+    }
+}
+
+/// Split the path of an entry into its names, leaving out `.`, `..` and the root
+pub(crate) fn components(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Copy the content of the file at `wanted` in a tar archive to `out`, returns its size
+///
+/// Reading stops at that file, `None` is returned when the archive has no such file
+pub(crate) fn read_tar_entry<R: Read, W: Write>(
+    archive: &mut Archive<R>,
+    source: &Path,
+    wanted: &[String],
+    out: W,
+    tracker: &Tracker<'_>,
+) -> Result<Option<u64>, Error> {
+    for entry in archive.entries().context(source, Phase::Decoding)? {
+        let mut entry = entry.context(source, Phase::Decoding)?;
+        let is_file = matches!(
+            entry.header().entry_type(),
+            EntryType::Regular | EntryType::Continuous
+        );
+        let path = entry.path().context(source, Phase::Decoding)?.into_owned();
+        tracker.read_entry(&path).context(source, Phase::Decoding)?;
+        if !is_file || components(&path) != wanted {
+            continue;
+        }
+
+        tracker
+            .start_entry(&path)
+            .and_then(|()| tracker.check_entry_size(entry.size()))
+            .context(source, Phase::Decoding)?;
+        let size = std::io::copy(&mut entry, &mut Tracked::new(out, tracker, Count::Written))
+            .context(source, Phase::Unpacking)?;
+        tracker.finish_entry();
+        return Ok(Some(size));
+    }
+    Ok(None)
+}
+
+/// Copy the content of the file at `wanted` in a zip archive to `out`, returns its size
+///
+/// Reading stops at that file, `None` is returned when the archive has no such file
+pub(crate) fn read_zip_entry<R: BufRead, W: Write>(
+    mut zip: ZipReader<R>,
+    source: &Path,
+    wanted: &[String],
+    out: W,
+    tracker: &Tracker<'_>,
+) -> Result<Option<u64>, Error> {
+    while let Some(entry) = zip.next_entry().context(source, Phase::Decoding)? {
+        let path = PathBuf::from(&*os_str(&entry.name));
+        tracker.read_entry(&path).context(source, Phase::Decoding)?;
+        if entry.is_dir() || components(&path) != wanted {
+            zip.read_data(&entry, &mut std::io::sink())
+                .context(source, Phase::Decoding)?;
+            continue;
+        }
+
+        tracker
+            .start_entry(&path)
+            .context(source, Phase::Decoding)?;
+        let size = zip
+            .read_data(&entry, &mut Tracked::new(out, tracker, Count::Written))
+            .context(source, Phase::Unpacking)?;
+        tracker.finish_entry();
+        return Ok(Some(size));
+    }
+    Ok(None)
+}
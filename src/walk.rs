@@ -0,0 +1,278 @@
+use std::{
+    fs::Metadata,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    error::ResultExt,
+    glob::{Glob, IgnoreRules},
+    Error, Phase,
+};
+
+/// A file or directory given to the compressor
+#[derive(Debug, Clone, PartialEq, PartialOrd, Hash)]
+pub(crate) struct Input {
+    pub(crate) path: PathBuf,
+    /// Name of the input inside the archive, instead of its own name
+    pub(crate) name: Option<PathBuf>,
+}
+
+impl Input {
+    pub(crate) fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            name: None,
+        }
+    }
+}
+
+/// A file system entry that goes into an archive
+pub(crate) struct InputEntry {
+    /// Where the entry is read from
+    pub(crate) source: PathBuf,
+    /// The relative path of the entry inside the archive
+    pub(crate) name: PathBuf,
+    /// The metadata of `source`, symlinks are only followed if the walk follows them
+    pub(crate) metadata: Metadata,
+}
+
+/// Options used to collect the entries of the inputs
+pub(crate) struct Walk<'a> {
+    /// Archive the targets of symlinks instead of the symlinks themselves
+    pub(crate) follow_symlinks: bool,
+    /// Left out of the archive, used to not archive the output into itself
+    pub(crate) skip: Option<&'a Path>,
+    /// Only the content of directories matching one of these is archived, everything if empty
+    pub(crate) include: &'a [Glob],
+    /// The content of directories matching one of these is left out
+    pub(crate) exclude: &'a [Glob],
+    /// Leave out what `.gitignore` and `.ignore` files match, and `.git` directories
+    pub(crate) respect_ignore_files: bool,
+    /// Store the content of directories without a name at the root of the archive
+    pub(crate) contents_at_root: bool,
+    /// Visit the children of directories sorted by name, so the order does not depend on the file system
+    pub(crate) sorted: bool,
+}
+
+/// The state of the walk of an input directory
+struct DirWalk {
+    /// The canonical paths of the directories being walked
+    ancestors: Vec<PathBuf>,
+    /// The path of the current entry relative to the input directory
+    relative: Vec<String>,
+    /// The ignore rules of the directories being walked, with the depth they apply from
+    ignores: Vec<(usize, IgnoreRules)>,
+    skip: Option<PathBuf>,
+}
+
+impl Walk<'_> {
+    /// Collect the entries to archive for all `inputs`, in order
+    pub(crate) fn entries(&self, inputs: &[Input]) -> Result<Vec<InputEntry>, Error> {
+        let mut entries = Vec::new();
+        for input in inputs {
+            self.input_entries(&input.path, input.name.as_deref(), &mut entries)?;
+        }
+        Ok(entries)
+    }
+
+    /// Collect the entries to archive for `input`
+    ///
+    /// A directory is stored under its own name followed by all of its content, parents
+    /// always come before their children. A file is stored under its path, without
+    /// the leading root and `.`/`..` components. Both can be renamed with `root_name`.
+    /// Without a `root_name` and with `contents_at_root`, the content of a directory is
+    /// stored at the root and the directory itself is left out.
+    fn input_entries(
+        &self,
+        input: &Path,
+        root_name: Option<&Path>,
+        entries: &mut Vec<InputEntry>,
+    ) -> Result<(), Error> {
+        let metadata = self.metadata(input)?;
+
+        if metadata.is_dir() {
+            // `.` and `..` have no name of their own, use the name of the directory they point to
+            let canonical = input.canonicalize().context(input, Phase::BuildingTar)?;
+            let name = root_name
+                .map(normalize)
+                .or_else(|| self.contents_at_root.then(PathBuf::new))
+                .or_else(|| input.file_name().map(PathBuf::from))
+                .or_else(|| canonical.file_name().map(PathBuf::from))
+                .ok_or_else(|| Error::UnsupportedInputKind {
+                    path: input.to_path_buf(),
+                })?;
+            let mut state = DirWalk {
+                ancestors: Vec::new(),
+                relative: Vec::new(),
+                ignores: Vec::new(),
+                skip: self.skip.and_then(|skip| skip.canonicalize().ok()),
+            };
+            let included = self.include.is_empty();
+            self.walk_dir(input, name, metadata, included, &mut state, entries)?;
+        } else if metadata.is_file() || metadata.is_symlink() {
+            entries.push(InputEntry {
+                source: input.to_path_buf(),
+                name: normalize(root_name.unwrap_or(input)),
+                metadata,
+            });
+        } else {
+            return Err(Error::UnsupportedInputKind {
+                path: input.to_path_buf(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Walk the directory at `source`, `included` is whether it matched an include pattern
+    fn walk_dir(
+        &self,
+        source: &Path,
+        name: PathBuf,
+        metadata: Metadata,
+        included: bool,
+        state: &mut DirWalk,
+        entries: &mut Vec<InputEntry>,
+    ) -> Result<(), Error> {
+        // When symlinks are followed, a link to one of its own parents would never end
+        let canonical = source.canonicalize().context(source, Phase::BuildingTar)?;
+        if state.ancestors.contains(&canonical) {
+            return Ok(());
+        }
+
+        let index = entries.len();
+        // An input directory stored at the root has no entry of its own
+        if !name.as_os_str().is_empty() {
+            entries.push(InputEntry {
+                source: source.to_path_buf(),
+                name: name.clone(),
+                metadata,
+            });
+        }
+
+        let ignores = state.ignores.len();
+        if self.respect_ignore_files {
+            let rules = read_ignore_rules(source)?;
+            if !rules.is_empty() {
+                state.ignores.push((state.relative.len(), rules));
+            }
+        }
+
+        state.ancestors.push(canonical);
+        let mut children = std::fs::read_dir(source)
+            .and_then(|children| children.collect::<Result<Vec<_>, _>>())
+            .context(source, Phase::BuildingTar)?;
+        if self.sorted {
+            children.sort_by_key(std::fs::DirEntry::file_name);
+        }
+        for child in children {
+            let child_source = child.path();
+            let child_name = name.join(child.file_name());
+            let metadata = self.metadata(&child_source)?;
+            let is_dir = metadata.is_dir();
+
+            state
+                .relative
+                .push(child.file_name().to_string_lossy().into_owned());
+            if !self.is_excluded(state, is_dir) {
+                let included = included || self.is_included(&state.relative, is_dir);
+                if is_dir {
+                    self.walk_dir(
+                        &child_source,
+                        child_name,
+                        metadata,
+                        included,
+                        state,
+                        entries,
+                    )?;
+                } else if included && !is_skipped(&child_source, state.skip.as_deref()) {
+                    entries.push(InputEntry {
+                        source: child_source,
+                        name: child_name,
+                        metadata,
+                    });
+                }
+            }
+            state.relative.pop();
+        }
+        state.ancestors.pop();
+        state.ignores.truncate(ignores);
+
+        // Directories are only there to hold the included entries, the input itself is always kept
+        if !included && entries.len() == index + 1 && !state.relative.is_empty() {
+            entries.truncate(index);
+        }
+
+        Ok(())
+    }
+
+    fn is_excluded(&self, state: &DirWalk, is_dir: bool) -> bool {
+        let path = &state.relative;
+        if self.respect_ignore_files {
+            if is_dir && path.last().is_some_and(|name| name == ".git") {
+                return true;
+            }
+            // Rules of deeper directories take precedence
+            let ignored = state
+                .ignores
+                .iter()
+                .rev()
+                .find_map(|(depth, rules)| rules.is_ignored(&path[*depth..], is_dir));
+            if ignored == Some(true) {
+                return true;
+            }
+        }
+        self.exclude.iter().any(|glob| glob.matches(path, is_dir))
+    }
+
+    fn is_included(&self, path: &[String], is_dir: bool) -> bool {
+        self.include.iter().any(|glob| glob.matches(path, is_dir))
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata, Error> {
+        if self.follow_symlinks {
+            std::fs::metadata(path)
+        } else {
+            std::fs::symlink_metadata(path)
+        }
+        .context(path, Phase::BuildingTar)
+    }
+}
+
+/// Read the `.gitignore` and `.ignore` files of `dir`, the rules of `.ignore` take precedence
+fn read_ignore_rules(dir: &Path) -> Result<IgnoreRules, Error> {
+    let mut rules = IgnoreRules::default();
+    for file_name in [".gitignore", ".ignore"] {
+        let path = dir.join(file_name);
+        match std::fs::read(&path) {
+            Ok(content) => rules.add(&String::from_utf8_lossy(&content)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(Error::io(path, Phase::BuildingTar, err)),
+        }
+    }
+    Ok(rules)
+}
+
+fn is_skipped(path: &Path, skip: Option<&Path>) -> bool {
+    let Some(skip) = skip else {
+        return false;
+    };
+    // Only canonicalize the candidates that can match
+    path.file_name() == skip.file_name() && path.canonicalize().is_ok_and(|path| path == skip)
+}
+
+/// Check that `path` is relative without `..` components, so [`normalize`] only drops its `.` components
+pub(crate) fn is_plain(path: &Path) -> bool {
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Turn `path` into a relative path without `.` and `..` components
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect()
+}
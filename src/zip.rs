@@ -0,0 +1,738 @@
+use flate2::{bufread::DeflateDecoder, write::DeflateEncoder, Compression, CrcReader};
+use std::io::{self, copy, BufRead, Read, Seek, SeekFrom, Write};
+
+use crate::counter::{CountingReader, CountingWriter};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x0807_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+
+const FLAG_ENCRYPTED: u16 = 1;
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+const FLAG_UTF8: u16 = 1 << 11;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+/// Upper byte of "version made by", tells that the external attributes hold a unix mode
+const HOST_UNIX: u16 = 3;
+
+const EXTRA_ZIP64: u16 = 0x0001;
+const EXTRA_EXTENDED_TIMESTAMP: u16 = 0x5455;
+
+/// Sizes, offsets and counts from this value on are stored in the zip64 fields
+const ZIP64_LIMIT: u64 = 0xFFFF_FFFF;
+const ZIP64_COUNT_LIMIT: usize = 0xFFFF;
+
+/// Unix file type bits stored in the upper half of the external attributes
+pub(crate) const MODE_TYPE_MASK: u32 = 0o170_000;
+pub(crate) const MODE_DIRECTORY: u32 = 0o040_000;
+pub(crate) const MODE_SYMLINK: u32 = 0o120_000;
+/// MS-DOS directory attribute, for readers that ignore the unix mode
+const DOS_DIRECTORY: u32 = 0x10;
+
+/// How the file entries of a zip archive are compressed
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Method {
+    Stored,
+    Deflate(Compression),
+}
+
+/// The metadata of a zip entry
+#[derive(Debug, Clone)]
+pub(crate) struct ZipEntry {
+    /// Path inside the archive, using `/` as separator, not necessarily UTF-8
+    pub(crate) name: Vec<u8>,
+    /// Unix mode, including the file type bits
+    pub(crate) mode: u32,
+    /// Modification time in seconds since the unix epoch
+    pub(crate) mtime: u64,
+}
+
+struct Record {
+    entry: ZipEntry,
+    flags: u16,
+    method: u16,
+    crc: u32,
+    compressed_size: u64,
+    uncompressed_size: u64,
+    offset: u64,
+}
+
+impl Record {
+    fn needs_zip64(&self) -> bool {
+        self.compressed_size >= ZIP64_LIMIT
+            || self.uncompressed_size >= ZIP64_LIMIT
+            || self.offset >= ZIP64_LIMIT
+    }
+}
+
+/// Writes a zip archive entry by entry, without seeking in the output
+///
+/// File entries compressed with deflate are followed by a data descriptor, stored
+/// entries are read twice to know their CRC before the data is written
+pub(crate) struct ZipWriter<W: Write> {
+    writer: CountingWriter<W>,
+    method: Method,
+    records: Vec<Record>,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub(crate) fn new(writer: W, method: Method) -> Self {
+        Self {
+            writer: CountingWriter::new(writer),
+            method,
+            records: Vec::new(),
+        }
+    }
+
+    pub(crate) fn add_directory(&mut self, mut entry: ZipEntry) -> io::Result<()> {
+        if !entry.name.ends_with(b"/") {
+            entry.name.push(b'/');
+        }
+        let record = Record {
+            flags: name_flags(&entry.name),
+            entry,
+            method: METHOD_STORED,
+            crc: 0,
+            compressed_size: 0,
+            uncompressed_size: 0,
+            offset: self.writer.count(),
+        };
+        self.write_local_header(&record, false)?;
+        self.records.push(record);
+        Ok(())
+    }
+
+    /// Add a file entry with the content of `data`, returns the uncompressed size
+    pub(crate) fn add_file<R: Read + Seek>(
+        &mut self,
+        entry: ZipEntry,
+        data: &mut R,
+    ) -> io::Result<u64> {
+        let start = data.stream_position()?;
+        let size_hint = data.seek(SeekFrom::End(0))? - start;
+        data.seek(SeekFrom::Start(start))?;
+        let offset = self.writer.count();
+
+        let record = match self.method {
+            Method::Stored => {
+                let mut crc_reader = CrcReader::new(&mut *data);
+                let size = copy(&mut crc_reader, &mut io::sink())?;
+                let crc = crc_reader.crc().sum();
+                data.seek(SeekFrom::Start(start))?;
+
+                let record = Record {
+                    flags: name_flags(&entry.name),
+                    entry,
+                    method: METHOD_STORED,
+                    crc,
+                    compressed_size: size,
+                    uncompressed_size: size,
+                    offset,
+                };
+                self.write_local_header(&record, size >= ZIP64_LIMIT)?;
+                if copy(&mut data.take(size), &mut self.writer)? != size {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "file shrunk while being archived",
+                    ));
+                }
+                record
+            }
+            Method::Deflate(level) => {
+                // Incompressible data grows a little with deflate, keep a margin
+                let zip64 = size_hint >= ZIP64_LIMIT - (ZIP64_LIMIT >> 8);
+                let mut record = Record {
+                    flags: name_flags(&entry.name) | FLAG_DATA_DESCRIPTOR,
+                    entry,
+                    method: METHOD_DEFLATE,
+                    crc: 0,
+                    compressed_size: 0,
+                    uncompressed_size: 0,
+                    offset,
+                };
+                self.write_local_header(&record, zip64)?;
+
+                let data_start = self.writer.count();
+                let mut crc_reader = CrcReader::new(&mut *data);
+                let mut encoder = DeflateEncoder::new(&mut self.writer, level);
+                record.uncompressed_size = copy(&mut crc_reader, &mut encoder)?;
+                encoder.finish()?;
+                record.crc = crc_reader.crc().sum();
+                record.compressed_size = self.writer.count() - data_start;
+
+                write_u32(&mut self.writer, DATA_DESCRIPTOR_SIGNATURE)?;
+                write_u32(&mut self.writer, record.crc)?;
+                if zip64 {
+                    write_u64(&mut self.writer, record.compressed_size)?;
+                    write_u64(&mut self.writer, record.uncompressed_size)?;
+                } else if record.compressed_size >= ZIP64_LIMIT
+                    || record.uncompressed_size >= ZIP64_LIMIT
+                {
+                    return Err(io::Error::other("file grew while being archived"));
+                } else {
+                    write_u32(&mut self.writer, record.compressed_size as u32)?;
+                    write_u32(&mut self.writer, record.uncompressed_size as u32)?;
+                }
+                record
+            }
+        };
+
+        let size = record.uncompressed_size;
+        self.records.push(record);
+        Ok(size)
+    }
+
+    /// Write the central directory and return the underlying writer
+    pub(crate) fn finish(mut self) -> io::Result<CountingWriter<W>> {
+        let directory_offset = self.writer.count();
+        for record in &self.records {
+            write_central_header(&mut self.writer, record)?;
+        }
+        let directory_size = self.writer.count() - directory_offset;
+        let count = self.records.len();
+
+        if count >= ZIP64_COUNT_LIMIT
+            || directory_offset >= ZIP64_LIMIT
+            || directory_size >= ZIP64_LIMIT
+        {
+            let zip64_offset = self.writer.count();
+            let w = &mut self.writer;
+            write_u32(w, ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE)?;
+            write_u64(w, 44)?;
+            write_u16(w, (HOST_UNIX << 8) | VERSION_ZIP64)?;
+            write_u16(w, VERSION_ZIP64)?;
+            write_u32(w, 0)?;
+            write_u32(w, 0)?;
+            write_u64(w, count as u64)?;
+            write_u64(w, count as u64)?;
+            write_u64(w, directory_size)?;
+            write_u64(w, directory_offset)?;
+
+            write_u32(w, ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE)?;
+            write_u32(w, 0)?;
+            write_u64(w, zip64_offset)?;
+            write_u32(w, 1)?;
+        }
+
+        let w = &mut self.writer;
+        write_u32(w, END_OF_CENTRAL_DIRECTORY_SIGNATURE)?;
+        write_u16(w, 0)?;
+        write_u16(w, 0)?;
+        write_u16(w, count.min(ZIP64_COUNT_LIMIT) as u16)?;
+        write_u16(w, count.min(ZIP64_COUNT_LIMIT) as u16)?;
+        write_u32(w, directory_size.min(ZIP64_LIMIT) as u32)?;
+        write_u32(w, directory_offset.min(ZIP64_LIMIT) as u32)?;
+        write_u16(w, 0)?;
+
+        Ok(self.writer)
+    }
+
+    fn write_local_header(&mut self, record: &Record, zip64: bool) -> io::Result<()> {
+        let (time, date) = dos_datetime(record.entry.mtime);
+        let mut extra = extended_timestamp(record.entry.mtime);
+        if zip64 {
+            write_u16(&mut extra, EXTRA_ZIP64)?;
+            write_u16(&mut extra, 16)?;
+            write_u64(&mut extra, record.uncompressed_size)?;
+            write_u64(&mut extra, record.compressed_size)?;
+        }
+        let (compressed_size, uncompressed_size) = if zip64 {
+            (ZIP64_LIMIT as u32, ZIP64_LIMIT as u32)
+        } else {
+            (
+                record.compressed_size as u32,
+                record.uncompressed_size as u32,
+            )
+        };
+
+        let w = &mut self.writer;
+        write_u32(w, LOCAL_HEADER_SIGNATURE)?;
+        write_u16(
+            w,
+            if zip64 {
+                VERSION_ZIP64
+            } else {
+                VERSION_DEFAULT
+            },
+        )?;
+        write_u16(w, record.flags)?;
+        write_u16(w, record.method)?;
+        write_u16(w, time)?;
+        write_u16(w, date)?;
+        write_u32(w, record.crc)?;
+        write_u32(w, compressed_size)?;
+        write_u32(w, uncompressed_size)?;
+        write_u16(w, name_len(&record.entry.name)?)?;
+        write_u16(w, extra.len() as u16)?;
+        w.write_all(&record.entry.name)?;
+        w.write_all(&extra)
+    }
+}
+
+fn write_central_header<W: Write>(w: &mut W, record: &Record) -> io::Result<()> {
+    let (time, date) = dos_datetime(record.entry.mtime);
+    let zip64 = record.needs_zip64();
+    let version = if zip64 {
+        VERSION_ZIP64
+    } else {
+        VERSION_DEFAULT
+    };
+
+    let mut extra = Vec::new();
+    if zip64 {
+        let mut fields = Vec::new();
+        for value in [
+            record.uncompressed_size,
+            record.compressed_size,
+            record.offset,
+        ] {
+            if value >= ZIP64_LIMIT {
+                write_u64(&mut fields, value)?;
+            }
+        }
+        write_u16(&mut extra, EXTRA_ZIP64)?;
+        write_u16(&mut extra, fields.len() as u16)?;
+        extra.extend(fields);
+    }
+    extra.extend(extended_timestamp(record.entry.mtime));
+
+    let mut attributes = record.entry.mode << 16;
+    if record.entry.mode & MODE_TYPE_MASK == MODE_DIRECTORY {
+        attributes |= DOS_DIRECTORY;
+    }
+
+    write_u32(w, CENTRAL_HEADER_SIGNATURE)?;
+    write_u16(w, (HOST_UNIX << 8) | version)?;
+    write_u16(w, version)?;
+    write_u16(w, record.flags)?;
+    write_u16(w, record.method)?;
+    write_u16(w, time)?;
+    write_u16(w, date)?;
+    write_u32(w, record.crc)?;
+    write_u32(w, record.compressed_size.min(ZIP64_LIMIT) as u32)?;
+    write_u32(w, record.uncompressed_size.min(ZIP64_LIMIT) as u32)?;
+    write_u16(w, name_len(&record.entry.name)?)?;
+    write_u16(w, extra.len() as u16)?;
+    write_u16(w, 0)?;
+    write_u16(w, 0)?;
+    write_u16(w, 0)?;
+    write_u32(w, attributes)?;
+    write_u32(w, record.offset.min(ZIP64_LIMIT) as u32)?;
+    w.write_all(&record.entry.name)?;
+    w.write_all(&extra)
+}
+
+fn name_len(name: &[u8]) -> io::Result<u16> {
+    u16::try_from(name.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "zip entry name is too long: {}",
+                String::from_utf8_lossy(name)
+            ),
+        )
+    })
+}
+
+/// Only mark names as UTF-8 when they are, others are kept as raw bytes
+fn name_flags(name: &[u8]) -> u16 {
+    if std::str::from_utf8(name).is_ok() {
+        FLAG_UTF8
+    } else {
+        0
+    }
+}
+
+fn extended_timestamp(mtime: u64) -> Vec<u8> {
+    let mut extra = Vec::with_capacity(9);
+    extra.extend(EXTRA_EXTENDED_TIMESTAMP.to_le_bytes());
+    extra.extend(5u16.to_le_bytes());
+    // Only the modification time is present
+    extra.push(1);
+    extra.extend((mtime.min(u64::from(u32::MAX)) as u32).to_le_bytes());
+    extra
+}
+
+/// An entry read from a local file header
+#[derive(Debug, Clone)]
+pub(crate) struct LocalEntry {
+    /// Raw path inside the archive
+    pub(crate) name: Vec<u8>,
+    /// Position of the local header in the archive
+    pub(crate) offset: u64,
+    /// Modification time in seconds since the unix epoch
+    pub(crate) mtime: u64,
+    flags: u16,
+    method: u16,
+    crc: u32,
+    compressed_size: u64,
+    uncompressed_size: u64,
+    zip64: bool,
+}
+
+impl LocalEntry {
+    pub(crate) fn is_dir(&self) -> bool {
+        self.name.ends_with(b"/")
+    }
+}
+
+/// An entry read from the central directory
+#[derive(Debug, Clone)]
+pub(crate) struct CentralEntry {
+    /// Raw path inside the archive
+    pub(crate) name: Vec<u8>,
+    /// Unix mode, if the archive was created on a unix system
+    pub(crate) mode: Option<u32>,
+    /// Position of the local header of the entry, the same as its [`LocalEntry::offset`]
+    pub(crate) offset: u64,
+}
+
+/// Reads a zip archive from the start, without seeking
+///
+/// Entries are read from their local headers in the order they are stored, the
+/// unix modes are only known once the central directory at the end is reached
+pub(crate) struct ZipReader<R> {
+    reader: CountingReader<R>,
+    /// A signature that was read but not handled yet
+    pending: Option<u32>,
+    /// No entry was read yet
+    at_start: bool,
+    /// Position of the first local header, to line the offsets of the central directory up with it
+    first_offset: Option<u64>,
+}
+
+impl<R: BufRead> ZipReader<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self {
+            reader: CountingReader::new(reader),
+            pending: None,
+            at_start: true,
+            first_offset: None,
+        }
+    }
+
+    /// Read the header of the next entry, `None` once all entries were read
+    ///
+    /// The data of the previous entry must have been read with [`ZipReader::read_data`]
+    pub(crate) fn next_entry(&mut self) -> io::Result<Option<LocalEntry>> {
+        let mut signature = match self.pending {
+            Some(signature) => signature,
+            None => read_u32(&mut self.reader)?,
+        };
+        // A spanned archive in a single segment starts with the data descriptor signature as a marker
+        if std::mem::take(&mut self.at_start) && signature == DATA_DESCRIPTOR_SIGNATURE {
+            signature = read_u32(&mut self.reader)?;
+        }
+        if signature != LOCAL_HEADER_SIGNATURE {
+            return if is_directory_signature(signature) {
+                self.pending = Some(signature);
+                Ok(None)
+            } else {
+                Err(invalid_data("invalid zip local header signature"))
+            };
+        }
+
+        let offset = self.reader.count() - 4;
+        self.first_offset.get_or_insert(offset);
+        let r = &mut self.reader;
+        let _version = read_u16(r)?;
+        let flags = read_u16(r)?;
+        let method = read_u16(r)?;
+        let time = read_u16(r)?;
+        let date = read_u16(r)?;
+        let crc = read_u32(r)?;
+        let mut compressed_size = u64::from(read_u32(r)?);
+        let mut uncompressed_size = u64::from(read_u32(r)?);
+        let name_len = read_u16(r)?;
+        let extra_len = read_u16(r)?;
+        let name = read_bytes(r, name_len.into())?;
+        let extra = read_bytes(r, extra_len.into())?;
+
+        let mut mtime = unix_time(time, date);
+        let mut zip64 = false;
+        for (id, data) in extra_fields(&extra) {
+            match id {
+                EXTRA_ZIP64 => {
+                    zip64 = true;
+                    let mut data = data;
+                    if uncompressed_size == ZIP64_LIMIT {
+                        uncompressed_size = read_u64(&mut data)?;
+                    }
+                    if compressed_size == ZIP64_LIMIT {
+                        compressed_size = read_u64(&mut data)?;
+                    }
+                }
+                EXTRA_EXTENDED_TIMESTAMP if data.len() >= 5 && data[0] & 1 == 1 => {
+                    mtime = u64::from(u32::from_le_bytes([data[1], data[2], data[3], data[4]]));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Some(LocalEntry {
+            name,
+            offset,
+            mtime,
+            flags,
+            method,
+            crc,
+            compressed_size,
+            uncompressed_size,
+            zip64,
+        }))
+    }
+
+    /// Decompress the data of `entry` into `out`, returns the uncompressed size
+    pub(crate) fn read_data<W: Write + ?Sized>(
+        &mut self,
+        entry: &LocalEntry,
+        out: &mut W,
+    ) -> io::Result<u64> {
+        if entry.flags & FLAG_ENCRYPTED != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "encrypted zip entries are not supported",
+            ));
+        }
+        let has_descriptor = entry.flags & FLAG_DATA_DESCRIPTOR != 0;
+
+        let (crc, size) = match entry.method {
+            METHOD_STORED if has_descriptor && entry.compressed_size == 0 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "stored zip entries with a data descriptor can not be streamed",
+                ))
+            }
+            METHOD_STORED => {
+                let mut reader = CrcReader::new((&mut self.reader).take(entry.compressed_size));
+                let size = copy(&mut reader, out)?;
+                if size != entry.compressed_size {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "zip entry data is truncated",
+                    ));
+                }
+                (reader.crc().sum(), size)
+            }
+            METHOD_DEFLATE => {
+                let mut reader = CrcReader::new(DeflateDecoder::new(&mut self.reader));
+                let size = copy(&mut reader, out)?;
+                (reader.crc().sum(), size)
+            }
+            method => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("unsupported zip compression method {method}"),
+                ))
+            }
+        };
+
+        let (expected_crc, expected_size) = if has_descriptor {
+            let r = &mut self.reader;
+            let mut expected_crc = read_u32(r)?;
+            // The descriptor signature is optional
+            if expected_crc == DATA_DESCRIPTOR_SIGNATURE {
+                expected_crc = read_u32(r)?;
+            }
+            let expected_size = if entry.zip64 {
+                let _compressed = read_u64(r)?;
+                read_u64(r)?
+            } else {
+                let _compressed = read_u32(r)?;
+                u64::from(read_u32(r)?)
+            };
+            (expected_crc, expected_size)
+        } else {
+            (entry.crc, entry.uncompressed_size)
+        };
+
+        if crc != expected_crc || size != expected_size {
+            return Err(invalid_data("zip entry checksum mismatch"));
+        }
+        Ok(size)
+    }
+
+    /// Read the central directory, once all entries were read
+    ///
+    /// Returns the underlying reader, positioned after the central directory
+    pub(crate) fn central_directory(mut self) -> io::Result<(Vec<CentralEntry>, R)> {
+        let mut entries: Vec<CentralEntry> = Vec::new();
+        loop {
+            let signature = match self.pending.take() {
+                Some(signature) => signature,
+                None => read_u32(&mut self.reader)?,
+            };
+            if signature != CENTRAL_HEADER_SIGNATURE {
+                // The offsets may not count a marker or other data before the first entry
+                let lowest = entries.iter().map(|entry| entry.offset).min();
+                if let (Some(first), Some(lowest)) = (self.first_offset, lowest) {
+                    for entry in &mut entries {
+                        entry.offset = entry.offset.saturating_add(first.saturating_sub(lowest));
+                    }
+                }
+                return Ok((entries, self.reader.into_inner()));
+            }
+
+            let r = &mut self.reader;
+            let made_by = read_u16(r)?;
+            // Version needed, flags, method, time, date and crc
+            read_bytes(r, 14)?;
+            let compressed_size = read_u32(r)?;
+            let uncompressed_size = read_u32(r)?;
+            let name_len = read_u16(r)?;
+            let extra_len = read_u16(r)?;
+            let comment_len = read_u16(r)?;
+            // Disk number and internal attributes
+            read_bytes(r, 4)?;
+            let attributes = read_u32(r)?;
+            let mut offset = u64::from(read_u32(r)?);
+            let name = read_bytes(r, name_len.into())?;
+            let extra = read_bytes(r, extra_len.into())?;
+            read_bytes(r, comment_len.into())?;
+
+            if let Some((_, mut data)) = extra_fields(&extra).find(|(id, _)| *id == EXTRA_ZIP64) {
+                // Only the fields that overflowed are stored, in this order
+                for size in [uncompressed_size, compressed_size] {
+                    if u64::from(size) == ZIP64_LIMIT {
+                        read_u64(&mut data)?;
+                    }
+                }
+                if offset == ZIP64_LIMIT {
+                    offset = read_u64(&mut data)?;
+                }
+            }
+
+            let mode =
+                (made_by >> 8 == HOST_UNIX && attributes >> 16 != 0).then_some(attributes >> 16);
+            entries.push(CentralEntry { name, mode, offset });
+        }
+    }
+}
+
+fn is_directory_signature(signature: u32) -> bool {
+    matches!(
+        signature,
+        CENTRAL_HEADER_SIGNATURE
+            | END_OF_CENTRAL_DIRECTORY_SIGNATURE
+            | ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE
+    )
+}
+
+fn extra_fields(mut extra: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if extra.len() < 4 {
+            return None;
+        }
+        let id = u16::from_le_bytes([extra[0], extra[1]]);
+        let len = usize::from(u16::from_le_bytes([extra[2], extra[3]]));
+        let data = extra.get(4..4 + len)?;
+        extra = &extra[4 + len..];
+        Some((id, data))
+    })
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_bytes<R: Read>(reader: &mut R, len: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 == len {
+        Ok(bytes)
+    } else {
+        Err(io::ErrorKind::UnexpectedEof.into())
+    }
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn write_u16<W: Write + ?Sized>(writer: &mut W, value: u16) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_u32<W: Write + ?Sized>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_u64<W: Write + ?Sized>(writer: &mut W, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+/// Convert a unix timestamp to the MS-DOS `(time, date)` pair, in UTC
+///
+/// MS-DOS dates start in 1980 and end in 2107, timestamps outside are clamped
+fn dos_datetime(mtime: u64) -> (u16, u16) {
+    let days = (mtime / 86_400) as i64;
+    let seconds = mtime % 86_400;
+    let (year, month, day) = civil_from_days(days);
+    if year < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    if year > 2107 {
+        return ((23 << 11) | (59 << 5) | 29, (127 << 9) | (12 << 5) | 31);
+    }
+
+    let time = ((seconds / 3600) << 11) | (((seconds % 3600) / 60) << 5) | ((seconds % 60) / 2);
+    let date = ((year - 1980) << 9) | (i64::from(month) << 5) | i64::from(day);
+    (time as u16, date as u16)
+}
+
+/// Convert an MS-DOS time and date, in UTC, to a unix timestamp
+fn unix_time(time: u16, date: u16) -> u64 {
+    let year = i64::from(date >> 9) + 1980;
+    let month = u32::from((date >> 5) & 0xf).clamp(1, 12);
+    let day = u32::from(date & 0x1f).max(1);
+    let seconds = u64::from(time >> 11) * 3600
+        + u64::from((time >> 5) & 0x3f) * 60
+        + u64::from(time & 0x1f) * 2;
+    days_from_civil(year, month, day) as u64 * 86_400 + seconds
+}
+
+/// Days since the unix epoch to a `(year, month, day)` date in the proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// A `(year, month, day)` date in the proleptic Gregorian calendar to days since the unix epoch
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = i64::from(if month > 2 { month - 3 } else { month + 9 });
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}